@@ -7,7 +7,10 @@ use std::{
 };
 
 use config::BLOCKS;
-use libc::{c_uint, c_void, sighandler_t, signal, SIGINT, SIGRTMIN, SIGTERM};
+use libc::{
+    c_uint, c_void, sigaction, sigemptyset, sighandler_t, siginfo_t, signal,
+    SA_SIGINFO, SIGINT, SIGRTMIN, SIGTERM,
+};
 use x11::xlib::{
     XCloseDisplay, XDefaultScreen, XOpenDisplay, XRootWindow, XStoreName,
 };
@@ -21,11 +24,17 @@ struct Globals<const N: usize> {
     statusstr: [String; 2],
 }
 
-fn getsigcmds<const N: usize>(g: *mut Globals<N>, signal: c_int) {
+/// Update the blocks for `signal`, passing along the mouse `button` that was
+/// clicked on them in rwm, if any.
+fn getsigcmds<const N: usize>(
+    g: *mut Globals<N>,
+    signal: c_int,
+    button: c_int,
+) {
     for (i, current) in BLOCKS.iter().enumerate() {
         if current.signal == signal {
             unsafe {
-                (*g).statusbar[i] = current.getcmd();
+                (*g).statusbar[i] = current.getcmd(button);
             }
         }
     }
@@ -79,7 +88,7 @@ impl<const N: usize> Globals<N> {
             if current.interval != 0 && time % current.interval as i32 == 0
                 || time == -1
             {
-                self.statusbar[i] = current.getcmd();
+                self.statusbar[i] = current.getcmd(0);
             }
         }
     }
@@ -95,8 +104,11 @@ extern "C" fn termhandler(_: c_int) {
     std::process::exit(0);
 }
 
-extern "C" fn sighandler(signum: c_int) {
-    getsigcmds(&raw mut GLOB, signum - SIGRTMIN());
+/// rwm sends the button clicked on a block as the value of the signal, while
+/// signals from `pkill` have a value of 0.
+extern "C" fn sighandler(signum: c_int, info: *mut siginfo_t, _: *mut c_void) {
+    let button = unsafe { (*info).si_value().sival_ptr as usize as c_int };
+    getsigcmds(&raw mut GLOB, signum - SIGRTMIN(), button);
     writestatus(&raw mut GLOB);
 }
 
@@ -108,19 +120,32 @@ struct Block {
 }
 
 impl Block {
-    fn getcmd(&self) -> String {
+    /// Run the block's command, setting `BLOCK_BUTTON` if `button` is nonzero.
+    /// Blocks with a signal are marked with rwm's `^sig(n)` escape so that
+    /// clicks on them are sent back as that signal.
+    fn getcmd(&self, button: c_int) -> String {
         let mut output = String::new();
         use std::fmt::Write;
+        if self.signal > 0 {
+            write!(output, "^sig({})", self.signal).unwrap();
+        }
         write!(output, "{}", self.icon).unwrap();
-        let out = match Command::new(self.command).output() {
+        let mut cmd = Command::new(self.command);
+        if button != 0 {
+            cmd.env("BLOCK_BUTTON", button.to_string());
+        }
+        let out = match cmd.output() {
             Ok(output) => output,
             Err(e) => {
                 eprintln!("command `{}` failed with `{e}`", self.command);
                 return String::new();
             }
         };
-        write!(output, "{}{DELIM}", String::from_utf8(out.stdout).unwrap())
-            .unwrap();
+        write!(output, "{}", String::from_utf8(out.stdout).unwrap()).unwrap();
+        if self.signal > 0 {
+            write!(output, "^sig()").unwrap();
+        }
+        write!(output, "{DELIM}").unwrap();
         output
     }
 }
@@ -129,7 +154,13 @@ fn setupsignals() {
     for block in &BLOCKS {
         if block.signal > 0 {
             unsafe {
-                signal(SIGRTMIN() + block.signal, get_handler(sighandler));
+                let mut sa: sigaction = std::mem::zeroed();
+                sa.sa_sigaction = sighandler
+                    as extern "C" fn(c_int, *mut siginfo_t, *mut c_void)
+                    as sighandler_t;
+                sa.sa_flags = SA_SIGINFO;
+                sigemptyset(&mut sa.sa_mask);
+                sigaction(SIGRTMIN() + block.signal, &sa, std::ptr::null_mut());
             }
         }
     }
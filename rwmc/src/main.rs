@@ -0,0 +1,359 @@
+//! command-line client for the rwm IPC socket
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    process::ExitCode,
+};
+
+use rwm::{
+    Arg,
+    config::key::{ArgKind, arg_kind},
+    ipc::{Query, Reply, Request, socket_path},
+};
+
+const USAGE: &str = "\
+usage: rwmc <handler> [ARG...]
+       rwmc get <monitors|clients|tags|layouts> [--json]
+       rwmc subscribe [EVENT...]
+
+Call one of the handlers available to key bindings in config.lua, or print part
+of the current window manager state. `subscribe` prints one line of JSON for
+each event of the given kinds, or of every kind if none are given, until rwm
+exits. EVENT is one of focus, tags, layout, manage, unmanage, fullscreen,
+urgent, or monitors.
+
+ARG is parsed according to the handler: a tag number, 0 for the previous tags,
+or `all` for view, toggleview, tag, and toggletag; a layout index, name, or
+symbol for setlayout; a command line for spawn; a scratchpad name for
+togglescratch; and a number for the rest. A single ARG starting with `{` is
+instead parsed as JSON with the same shape as an `arg` table in config.lua,
+such as '{\"Ui\": 5}'.";
+
+struct Connection {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Connection {
+    fn open() -> Result<Self, String> {
+        let display =
+            std::env::var("DISPLAY").map_err(|_| "DISPLAY is not set")?;
+        let path = socket_path(&display);
+        let writer = UnixStream::connect(&path).map_err(|e| {
+            format!("failed to connect to {}: {e}", path.display())
+        })?;
+        let reader = BufReader::new(writer.try_clone().map_err(|e| {
+            format!("failed to connect to {}: {e}", path.display())
+        })?);
+        Ok(Self { reader, writer })
+    }
+
+    /// Send `request` and return the raw JSON reply.
+    fn request_raw(&mut self, request: &Request) -> Result<String, String> {
+        let mut line = serde_json::to_string(request).unwrap();
+        line.push('\n');
+        self.writer
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("failed to send request: {e}"))?;
+        let mut reply = String::new();
+        self.reader
+            .read_line(&mut reply)
+            .map_err(|e| format!("failed to read reply: {e}"))?;
+        if reply.is_empty() {
+            return Err("connection closed without a reply".to_owned());
+        }
+        Ok(reply.trim_end().to_owned())
+    }
+
+    fn request(&mut self, request: &Request) -> Result<Reply, String> {
+        let reply = self.request_raw(request)?;
+        match serde_json::from_str(&reply) {
+            Ok(Reply::Error { message }) => Err(message),
+            Ok(reply) => Ok(reply),
+            Err(e) => Err(format!("invalid reply `{reply}`: {e}")),
+        }
+    }
+}
+
+/// Resolve `name` to an index into the configured layouts, by name of the
+/// arrange function or symbol.
+fn layout(conn: &mut Connection, name: &str) -> Result<usize, String> {
+    let Reply::Layouts { layouts } =
+        conn.request(&Request::Get { what: Query::Layouts })?
+    else {
+        return Err("unexpected reply to layout query".to_owned());
+    };
+    layouts
+        .iter()
+        .find(|l| l.name.as_deref() == Some(name) || l.symbol == name)
+        .map(|l| l.index)
+        .ok_or_else(|| format!("no layout named `{name}`"))
+}
+
+/// Parse the command-line `args` into the [Arg] expected by the handler
+/// `func`, resolving layout names with `layout`.
+fn parse_arg(
+    func: &str,
+    args: &[String],
+    layout: impl FnOnce(&str) -> Result<usize, String>,
+) -> Result<Arg, String> {
+    if let [arg] = args
+        && arg.starts_with('{')
+    {
+        return serde_json::from_str(arg)
+            .map_err(|e| format!("invalid argument `{arg}`: {e}"));
+    }
+    let kind = arg_kind(func).ok_or_else(|| format!("no handler `{func}`"))?;
+    if kind == ArgKind::V {
+        if args.is_empty() {
+            return Err(format!("`{func}` requires a command"));
+        }
+        return Ok(Arg::V(args.to_vec()));
+    }
+    let arg = match args {
+        [] => None,
+        [arg] => Some(arg.as_str()),
+        _ => return Err(format!("too many arguments for `{func}`")),
+    };
+    let invalid = |e: &dyn std::fmt::Display| {
+        format!("invalid argument `{}` for `{func}`: {e}", arg.unwrap())
+    };
+    match (kind, arg) {
+        (ArgKind::Unused, None) => Ok(Arg::L(None)),
+        (ArgKind::Unused, Some(_)) => {
+            Err(format!("`{func}` does not take an argument"))
+        }
+        (ArgKind::L, None) => Ok(Arg::L(None)),
+        (ArgKind::L, Some(s)) => match s.parse() {
+            Ok(i) => Ok(Arg::L(Some(i))),
+            Err(_) => Ok(Arg::L(Some(layout(s)?))),
+        },
+        (ArgKind::Lua, _) => {
+            Err(format!("`{func}` can only be bound in config.lua"))
+        }
+        (_, None) => Err(format!("`{func}` requires an argument")),
+        (ArgKind::I, Some(s)) => s.parse().map(Arg::I).map_err(|e| invalid(&e)),
+        (ArgKind::F, Some(s)) => s.parse().map(Arg::F).map_err(|e| invalid(&e)),
+        (ArgKind::Name, Some(s)) => Ok(Arg::Name(s.to_owned())),
+        (ArgKind::Ui, Some("all")) => Ok(Arg::Ui(!0)),
+        (ArgKind::Ui, Some(s)) => match s.parse::<u32>() {
+            Ok(0) => Ok(Arg::Ui(0)),
+            Ok(n @ 1..=32) => Ok(Arg::Ui(1 << (n - 1))),
+            Ok(n) => Err(invalid(&format!("no tag {n}"))),
+            Err(e) => Err(invalid(&e)),
+        },
+        (ArgKind::V, _) => unreachable!(),
+    }
+}
+
+/// Format the set bits of `mask` as a comma-separated list of tag numbers.
+fn tag_list(mask: u32) -> String {
+    (0..32)
+        .filter(|i| mask & (1 << i) != 0)
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn print_reply(reply: Reply) {
+    match reply {
+        Reply::Ok | Reply::Error { .. } | Reply::Event { .. } => {}
+        Reply::Monitors { monitors } => {
+            for m in monitors {
+                let g = m.geometry;
+                println!(
+                    "{}{} {}x{}+{}+{} tags={} {}",
+                    m.num,
+                    if m.selected { "*" } else { "" },
+                    g.w,
+                    g.h,
+                    g.x,
+                    g.y,
+                    tag_list(m.tagset),
+                    m.layout,
+                );
+            }
+        }
+        Reply::Clients { clients } => {
+            for c in clients {
+                let flags: Vec<_> = [
+                    (c.focused, "focused"),
+                    (c.isfloating, "floating"),
+                    (c.isfullscreen, "fullscreen"),
+                    (c.isurgent, "urgent"),
+                    (c.ishidden, "hidden"),
+                ]
+                .into_iter()
+                .filter_map(|(set, flag)| set.then_some(flag))
+                .collect();
+                println!(
+                    "{:#010x} mon={} tags={} [{}] {}",
+                    c.window,
+                    c.monitor,
+                    tag_list(c.tags),
+                    flags.join(","),
+                    c.name,
+                );
+            }
+        }
+        Reply::Tags { tags } => {
+            for tag in tags {
+                println!("{tag}");
+            }
+        }
+        Reply::Layouts { layouts } => {
+            for l in layouts {
+                println!(
+                    "{} {} {}",
+                    l.index,
+                    l.symbol,
+                    l.name.as_deref().unwrap_or("floating")
+                );
+            }
+        }
+    }
+}
+
+fn get(conn: &mut Connection, args: &[String]) -> Result<(), String> {
+    let (what, json) = match args {
+        [what] => (what, false),
+        [what, flag] if flag == "--json" => (what, true),
+        _ => return Err(USAGE.to_owned()),
+    };
+    let what = serde_json::from_value(serde_json::Value::from(what.as_str()))
+        .map_err(|_| format!("unknown query `{what}`\n\n{USAGE}"))?;
+    let request = Request::Get { what };
+    if json {
+        println!("{}", conn.request_raw(&request)?);
+    } else {
+        print_reply(conn.request(&request)?);
+    }
+    Ok(())
+}
+
+fn subscribe(conn: &mut Connection, args: &[String]) -> Result<(), String> {
+    let events = args
+        .iter()
+        .map(|e| {
+            serde_json::from_value(serde_json::Value::from(e.as_str()))
+                .map_err(|_| format!("unknown event `{e}`\n\n{USAGE}"))
+        })
+        .collect::<Result<_, _>>()?;
+    conn.request(&Request::Subscribe { events })?;
+    let mut stdout = std::io::stdout();
+    for line in conn.reader.by_ref().lines() {
+        let line = line.map_err(|e| format!("failed to read event: {e}"))?;
+        // stop quietly when the output is closed, such as by `head`
+        if writeln!(stdout, "{line}")
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let Some((cmd, rest)) = args.split_first() else {
+        return Err(USAGE.to_owned());
+    };
+    if cmd == "-h" || cmd == "--help" {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let mut conn = Connection::open()?;
+    if cmd == "get" {
+        return get(&mut conn, rest);
+    }
+    if cmd == "subscribe" {
+        return subscribe(&mut conn, rest);
+    }
+    let arg = parse_arg(cmd, rest, |name| layout(&mut conn, name))?;
+    conn.request(&Request::Command { name: cmd.clone(), arg })?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rwmc: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(func: &str, args: &[&str]) -> Result<Arg, String> {
+        let args: Vec<String> = args.iter().map(|&s| s.to_owned()).collect();
+        parse_arg(func, &args, |name| match name {
+            "monocle" | "[M]" => Ok(2),
+            _ => Err(format!("no layout named `{name}`")),
+        })
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(parse("view", &["1"]), Ok(Arg::Ui(1)));
+        assert_eq!(parse("toggletag", &["3"]), Ok(Arg::Ui(4)));
+        assert_eq!(parse("tag", &["all"]), Ok(Arg::Ui(!0)));
+        assert_eq!(parse("view", &["0"]), Ok(Arg::Ui(0)));
+        assert!(parse("view", &["33"]).unwrap_err().contains("no tag 33"));
+        assert!(parse("view", &["x"]).is_err());
+        assert!(parse("view", &[]).unwrap_err().contains("requires"));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse("focusstack", &["-1"]), Ok(Arg::I(-1)));
+        assert_eq!(parse("setmfact", &["0.05"]), Ok(Arg::F(0.05)));
+        assert!(parse("incnmaster", &["1.5"]).is_err());
+        assert!(
+            parse("focusstack", &["1", "2"])
+                .unwrap_err()
+                .contains("many")
+        );
+    }
+
+    #[test]
+    fn layouts() {
+        assert_eq!(parse("setlayout", &[]), Ok(Arg::L(None)));
+        assert_eq!(parse("setlayout", &["1"]), Ok(Arg::L(Some(1))));
+        assert_eq!(parse("setlayout", &["monocle"]), Ok(Arg::L(Some(2))));
+        assert_eq!(parse("setlayout", &["[M]"]), Ok(Arg::L(Some(2))));
+        assert!(parse("setlayout", &["spiral"]).is_err());
+    }
+
+    #[test]
+    fn others() {
+        assert_eq!(
+            parse("spawn", &["st", "-e", "htop"]),
+            Ok(Arg::V(vec!["st".into(), "-e".into(), "htop".into()]))
+        );
+        assert!(parse("spawn", &[]).is_err());
+        assert_eq!(
+            parse("togglescratch", &["term"]),
+            Ok(Arg::Name("term".into()))
+        );
+        assert_eq!(parse("zoom", &[]), Ok(Arg::L(None)));
+        assert!(parse("zoom", &["1"]).is_err());
+        assert!(parse("lua", &[]).is_err());
+        assert!(
+            parse("nosuchhandler", &[])
+                .unwrap_err()
+                .contains("no handler")
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(parse("view", &[r#"{"Ui": 5}"#]), Ok(Arg::Ui(5)));
+        assert!(parse("view", &["{"]).is_err());
+    }
+}
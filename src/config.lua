@@ -1,34 +1,69 @@
--- Constructor functions
+-- Returns the file and line of the first caller outside of this file, recorded
+-- by the constructors below so that `rwm --check-config` can point at them.
+local caller = ... or function () end
+
+-- Constructor functions. Each of these also takes a single table of named
+-- fields instead, like key{mod = modkey, keysym = XK_p, func = spawn, arg = ...},
+-- where any field that isn't needed can be left out
+
+-- A copy of the named fields in t, recording where it was called from
+local function named (t)
+   local r = {}
+   for k, v in pairs(t) do
+	  r[k] = v
+   end
+   r._loc = caller()
+   return r
+end
+
 function key (mod, keysym, func, arg)
+   if type(mod) == "table" then
+	  return named(mod)
+   end
    return {
 	  mod_ = mod,
 	  keysym = keysym,
 	  func = func,
 	  arg = arg,
+	  _loc = caller(),
    }
 end
 
 function button (click, mask, button, func, arg)
+   if type(click) == "table" then
+	  return named(click)
+   end
    return {
 	  click = click,
 	  mask = mask,
 	  button = button,
 	  func = func,
 	  arg = arg,
+	  _loc = caller(),
    }
 end
 
-function rule (class, instance, title, tags, isfloating, isterminal, noswallow, monitor)
-   return {
-	  class = class,
-	  instance = instance,
-	  title = title,
-	  tags = tags,
-	  isfloating = isfloating,
-	  isterminal = isterminal,
-	  noswallow = noswallow,
-	  monitor = monitor,
-   }
+-- class, instance, and title are regular expressions, and an empty one matches
+-- anything. The optional opts table can set any of the other rule fields, like
+-- {role = "pop-up", w = 0.5, h = 0.5, borderpx = 0, switchtotag = true}
+function rule (class, instance, title, tags, isfloating, isterminal, noswallow, monitor, opts)
+   if type(class) == "table" then
+	  return named(class)
+   end
+   local r = {}
+   for k, v in pairs(opts or {}) do
+	  r[k] = v
+   end
+   r.class = class
+   r.instance = instance
+   r.title = title
+   r.tags = tags
+   r.isfloating = isfloating
+   r.isterminal = isterminal
+   r.noswallow = noswallow
+   r.monitor = monitor
+   r._loc = caller()
+   return r
 end
 
 -- For each tag key (N = 1, 2, ...) you generally want to set four bindings:
@@ -88,7 +123,7 @@ all_tags = math.floor(2^32 - 1)
 keys = {
    key(modkey, XK_p, spawn, {V = dmenucmd}),
    key(s_mod, XK_Return, spawn, {V = termcmd}),
-   key(modkey, XK_grave, togglescratch, {V = scratchpadcmd}),
+   key(modkey, XK_grave, togglescratch, {name = "term"}),
    key(modkey, XK_b, togglebar, {I = 0}),
    key(modkey, XK_j, focusstack, {I = 1}),
    key(modkey, XK_k, focusstack, {I = -1}),
@@ -98,6 +133,15 @@ keys = {
    key(modkey, XK_d, incnmaster, {I = -1}),
    key(modkey, XK_h, setmfact, {F = -0.05}),
    key(modkey, XK_l, setmfact, {F = 0.05}),
+   key(s_mod, XK_h, setcfact, {F = -0.25}),
+   key(s_mod, XK_l, setcfact, {F = 0.25}),
+   key(s_mod, XK_o, setcfact, {F = 0}),
+   key(modkey, XK_minus, incgaps, {I = -1}),
+   key(modkey, XK_equal, incgaps, {I = 1}),
+   key(s_mod, XK_equal, togglegaps),
+   key(modkey, XK_n, hidewin),
+   key(modkey, XK_s, showwin),
+   key(s_mod, XK_s, showall),
    key(modkey, XK_Return, zoom, {I = 0}),
    key(modkey, XK_Tab, view, {Ui = 0}),
    key(s_mod, XK_c, killclient, {I = 0}),
@@ -121,12 +165,24 @@ rwm = {
    snap = 32,
    showbar = true,
    topbar = true,
+   -- Draw the bar with an ARGB visual so that #RRGGBBAA colors are translucent
+   -- when running a compositor. Only takes effect on restart
+   alphabar = false,
    mfact = 0.5,
    nmaster = 1,
+   -- Gap in pixels between tiled windows
+   gappi = 0,
+   -- Gap in pixels between tiled windows and the edge of the monitor
+   gappo = 0,
+   -- Whether to drop the gaps when there is only one tiled window
+   smartgaps = false,
    resize_hints = false,
    lock_fullscreen = true,
    fonts = {"monospace:size=10"},
    tags = {"1", "2", "3", "4", "5", "6", "7", "8", "9"},
+   -- Each scheme is {foreground, background, border}. Only norm and sel are
+   -- required: hid, urg, title_norm, title_sel, tag_occ, and status default to
+   -- colors based on them
    colors = {
 	  norm = {gray3, gray1, gray2},
 	  sel = {gray4, cyan, cyan},
@@ -134,7 +190,7 @@ rwm = {
    keys = keys,
    dmenucmd = dmenucmd,
    rules = {
-	  rule("st-256color", "", "", 0, false, true, false, -1),
+	  rule{class = "st-256color", isterminal = true},
    },
    -- Whether to swallow floating windows
    swallowfloating = false,
@@ -167,5 +223,14 @@ rwm = {
 	  {symbol = "><>", arrange = nil },
 	  {symbol = "[M]", arrange = monocle },
    },
-   scratchpadname = scratchpadname,
+   -- Windows shown and hidden by togglescratch. Each is spawned with cmd if it
+   -- isn't running and is recognized by its class and/or title. Optionally,
+   -- w, h, x, and y place it on the monitor, as fractions of its size up to 1
+   -- or in pixels, centering it if x or y is unset
+   scratchpads = {
+	  {name = "term", cmd = scratchpadcmd, title = scratchpadname},
+   },
+   -- Clicks on parts of the status text marked with ^sig(n) are sent to this
+   -- program as the signal SIGRTMIN+n
+   statusprogram = "rwmblocks",
 }
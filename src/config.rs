@@ -7,42 +7,73 @@ use std::{
 };
 
 use env::{CLICKS, HANDLERS, KEYS, XKEYS};
-use mlua::{Lua, LuaSerdeExt as _, Table};
+use mlua::{Lua, LuaSerdeExt as _, Table, Value};
 
-use crate::{Button, Layout, Rule, config::key::Key, enums::Scheme};
+use crate::{
+    Button, Layout, Rule, Scratchpad, config::key::Key, lua::LuaRuntime,
+};
 
+pub mod check;
 mod env;
 pub mod key;
 
+/// The builtin config, loaded before the user's config file
+pub const DEFAULT_CONFIG: &str = include_str!("config.lua");
+
 #[derive(Debug, serde::Deserialize)]
 #[serde(try_from = "HashMap<String, Vec<String>>")]
+/// Color schemes indexed by [Scheme](crate::enums::Scheme)
 pub struct ColorMap(pub Vec<Vec<CString>>);
 
+/// Take the scheme `name` from `value`, checking that it has all three colors.
+fn take_scheme(
+    value: &mut HashMap<String, Vec<String>>,
+    name: &str,
+) -> Result<Option<Vec<CString>>, Box<dyn Error>> {
+    let Some(scheme) = value.remove(name) else {
+        return Ok(None);
+    };
+    if scheme.len() != 3 {
+        return Err(format!(
+            "scheme {name} has {} colors, expected 3",
+            scheme.len()
+        )
+        .into());
+    }
+    Ok(Some(
+        scheme
+            .into_iter()
+            .map(CString::new)
+            .collect::<Result<_, _>>()?,
+    ))
+}
+
 impl TryFrom<HashMap<String, Vec<String>>> for ColorMap {
     type Error = Box<dyn Error>;
 
     fn try_from(
         mut value: HashMap<String, Vec<String>>,
     ) -> Result<Self, Self::Error> {
-        let mut ret = vec![vec![], vec![]];
-        let norm = value.remove("norm").ok_or("missing key norm")?;
-        let sel = value.remove("sel").ok_or("missing key sel")?;
-
-        if norm.len() != 3 {
-            return Err("not enough colors for SchemeNorm".into());
-        };
-        ret[Scheme::Norm as usize] = norm
-            .into_iter()
-            .map(CString::new)
-            .collect::<Result<_, _>>()?;
-
-        if sel.len() != 3 {
-            return Err("not enough colors for SchemeSel".into());
-        };
-        ret[Scheme::Sel as usize] = sel
-            .into_iter()
-            .map(CString::new)
-            .collect::<Result<_, _>>()?;
+        let norm =
+            take_scheme(&mut value, "norm")?.ok_or("missing key norm")?;
+        let sel = take_scheme(&mut value, "sel")?.ok_or("missing key sel")?;
+        let [fg, bg, border] = [0, 1, 2].map(|i| norm[i].clone());
+
+        // the rest are optional, with defaults that look the same as before
+        // they were added. These must be in the same order as Scheme
+        let mut ret = vec![norm.clone(), sel.clone()];
+        for (name, default) in [
+            // dim hidden windows by drawing them in the normal border color
+            ("hid", vec![border.clone(), bg.clone(), border.clone()]),
+            // urgent tags are drawn inverted, without changing the border
+            ("urg", vec![bg, fg, border]),
+            ("title_norm", norm.clone()),
+            ("title_sel", sel),
+            ("tag_occ", norm.clone()),
+            ("status", norm),
+        ] {
+            ret.push(take_scheme(&mut value, name)?.unwrap_or(default));
+        }
 
         Ok(Self(ret))
     }
@@ -62,12 +93,25 @@ pub struct Config {
     /// Whether to show the bar at the top or bottom
     pub topbar: bool,
 
+    /// Whether to draw the bar with an ARGB visual so that colors with an
+    /// alpha channel are translucent under a compositor
+    pub alphabar: bool,
+
     /// Factor of master area size [0.05..0.95]
     pub mfact: c_float,
 
     /// Number of clients in master area
     pub nmaster: c_int,
 
+    /// Gap in pixels between tiled windows
+    pub gappi: c_uint,
+
+    /// Gap in pixels between tiled windows and the edge of the monitor
+    pub gappo: c_uint,
+
+    /// Whether to drop the gaps when there is only one tiled window
+    pub smartgaps: bool,
+
     /// Respect size hints in tiled resizals
     pub resize_hints: bool,
 
@@ -106,7 +150,14 @@ pub struct Config {
 
     pub layouts: Vec<Layout>,
 
-    pub scratchpadname: String,
+    pub scratchpads: Vec<Scratchpad>,
+
+    /// Name of the status program to forward clicks on status blocks to
+    pub statusprogram: String,
+
+    /// The interpreter that loaded this config, for calling Lua callbacks
+    #[serde(skip)]
+    pub lua: LuaRuntime,
 }
 
 unsafe impl Send for Config {}
@@ -141,20 +192,58 @@ impl ConfigBuilder {
             globals.set(k, v).unwrap();
         }
 
-        lua.load(include_str!("config.lua")).exec().unwrap();
+        let caller = lua.create_function(|lua, ()| Ok(caller(lua))).unwrap();
+        lua.load(DEFAULT_CONFIG)
+            .set_name("=config.lua")
+            .call::<()>(caller)
+            .unwrap();
+        let rwm: Table = globals.get("rwm").unwrap();
+        crate::lua::install_api(&lua, &rwm).unwrap();
 
         Self { lua, globals }
     }
 
     /// Load and eval `path` into the Lua interpreter in `self`.
     fn load(self, path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
-        self.lua.load(read_to_string(path)?).exec()?;
+        let path = path.as_ref();
+        self.exec(&read_to_string(path)?, path)?;
         Ok(self)
     }
 
+    /// Eval `source`, naming the chunk after `path` for error messages.
+    fn exec(&self, source: &str, path: &Path) -> mlua::Result<()> {
+        self.lua
+            .load(source)
+            .set_name(format!("@{}", path.display()))
+            .exec()
+    }
+
     fn finish(self) -> Result<Config, Box<dyn Error>> {
-        Ok(self.lua.from_value(self.globals.get("rwm")?)?)
+        let rwm: Table = self.globals.get("rwm")?;
+        // in case the config replaced the whole table
+        crate::lua::install_api(&self.lua, &rwm)?;
+        let runtime = LuaRuntime::new(self.lua.clone(), &rwm)?;
+        let mut config: Config = self.lua.from_value(Value::Table(rwm))?;
+        runtime.set_layouts(&mut config.layouts);
+        config.lua = runtime;
+        Ok(config)
+    }
+}
+
+/// The `file:line` of the innermost function on the Lua stack that was loaded
+/// from a file, skipping the builtin `config.lua`.
+fn caller(lua: &Lua) -> Option<String> {
+    for level in 1.. {
+        let loc = lua.inspect_stack(level, |debug| {
+            let source = debug.source().source?.into_owned();
+            let file = source.strip_prefix('@')?.to_owned();
+            Some(format!("{file}:{}", debug.current_line()?))
+        })?;
+        if loc.is_some() {
+            return loc;
+        }
     }
+    None
 }
 
 impl Default for Config {
@@ -168,20 +257,27 @@ impl Config {
         ConfigBuilder::new().load(path)?.finish()
     }
 
-    /// Attempt to load a config file on first usage from `$XDG_CONFIG_HOME`,
-    /// then `$HOME`, before falling back to the default config.
-    pub fn load_home() -> Self {
+    /// The path of the config file in `$XDG_CONFIG_HOME`, or in `$HOME` if
+    /// that is unset.
+    pub fn home_path() -> Option<PathBuf> {
         let base = if let Ok(xdg_home) = std::env::var("XDG_CONFIG_HOME") {
             PathBuf::from(xdg_home)
         } else if let Ok(home) = std::env::var("HOME") {
             PathBuf::from(home).join(".config")
         } else {
+            return None;
+        };
+        Some(base.join("rwm").join("config.lua"))
+    }
+
+    /// Attempt to load a config file on first usage from `$XDG_CONFIG_HOME`,
+    /// then `$HOME`, before falling back to the default config.
+    pub fn load_home() -> Self {
+        let Some(config_path) = Self::home_path() else {
             log::warn!("unable to determine config directory");
             return Config::default();
         };
 
-        let config_path = base.join("rwm").join("config.lua");
-
         Config::from_lua(config_path).unwrap_or_else(|e| {
             log::error!("failed to read config file: {e:?}");
             Config::default()
@@ -200,4 +296,70 @@ mod tests {
         let got = Config::from_lua("testfiles/config.lua").unwrap();
         assert_debug_snapshot!(got)
     }
+
+    #[test]
+    fn named_fields() {
+        let builder = ConfigBuilder::new();
+        builder
+            .exec(
+                r#"
+                rwm.keys = {
+                   key(modkey, XK_a, zoom),
+                   key{mod = modkey, keysym = XK_a, func = zoom},
+                }
+                rwm.buttons = {
+                   button(ClkWinTitle, 0, Button2, zoom),
+                   button{click = ClkWinTitle, button = Button2, func = zoom},
+                }
+                rwm.rules = {
+                   rule("Firefox", "", "", 1 << 8, false, false, false, -1),
+                   rule{class = "Firefox", tags = 1 << 8},
+                }
+                "#,
+                Path::new("named.lua"),
+            )
+            .unwrap();
+        let config = builder.finish().unwrap();
+        let debug = |v: &dyn std::fmt::Debug| format!("{v:?}");
+        assert_eq!(debug(&config.keys[0]), debug(&config.keys[1]));
+        assert_eq!(debug(&config.buttons[0]), debug(&config.buttons[1]));
+        assert_eq!(debug(&config.rules[0]), debug(&config.rules[1]));
+    }
+
+    #[test]
+    fn lua_layouts() {
+        let builder = ConfigBuilder::new();
+        builder
+            .exec(
+                r#"
+                rwm.layouts = {
+                   {symbol = "[]=", arrange = tile},
+                   {symbol = "|||", arrange = function(m, clients) end},
+                   {symbol = "><>"},
+                }
+                "#,
+                Path::new("layouts.lua"),
+            )
+            .unwrap();
+        let config = builder.finish().unwrap();
+        let arrange: Vec<_> = config
+            .layouts
+            .iter()
+            .map(|l| format!("{:?}", l.arrange))
+            .collect();
+        assert_eq!(
+            arrange,
+            ["Some(LayoutFn(\"tile\"))", "Some(Lua(0))", "None"]
+        );
+
+        // Lua layouts can't be named by hand
+        let builder = ConfigBuilder::new();
+        builder
+            .exec(
+                r#"rwm.layouts = {{symbol = "|||", arrange = "lua:0"}}"#,
+                Path::new("layouts.lua"),
+            )
+            .unwrap();
+        assert!(builder.finish().is_err());
+    }
 }
@@ -0,0 +1,470 @@
+//! Validation of config files for `rwm --check-config`.
+//!
+//! Unlike [Config::from_lua], which stops at the first error, [check]
+//! deserializes each key, button, rule, and layout separately so that every
+//! problem can be reported at once, along with a few that deserialization
+//! alone can't catch.
+
+use std::{collections::HashMap, fmt::Display, fs::read_to_string, path::Path};
+
+use mlua::{Table, Value};
+use serde::de::DeserializeOwned;
+
+use super::{
+    Config, ConfigBuilder,
+    key::{Key, arg_kind},
+};
+use crate::layouts::geometry::Placement;
+use crate::{Arg, Button, Layout, Rule, Scratchpad, lua::LuaRuntime};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Either the `file:line` where the offending entry was constructed, or
+    /// the file and the path to the entry within the `rwm` table
+    pub location: String,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.location, self.severity, self.message)
+    }
+}
+
+struct Checker<'a> {
+    path: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn push(&mut self, severity: Severity, location: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            location: location.to_owned(),
+            message,
+        });
+    }
+
+    fn error(&mut self, location: &str, message: impl Into<String>) {
+        self.push(Severity::Error, location, message.into());
+    }
+
+    fn warning(&mut self, location: &str, message: impl Into<String>) {
+        self.push(Severity::Warning, location, message.into());
+    }
+
+    /// Check that `p` doesn't use 1 for a position, which looks like a pixel
+    /// but is the fraction putting the window past the right or bottom edge.
+    fn placement(&mut self, location: &str, p: &Placement) {
+        for (name, v) in [("x", p.x), ("y", p.y)] {
+            if v == Some(1.0) {
+                self.error(
+                    location,
+                    format!(
+                        "`{name} = 1` is a fraction that puts the window at \
+                         the edge of the monitor, use 0 or a number of pixels \
+                         greater than 1"
+                    ),
+                );
+            }
+        }
+    }
+
+    /// The location of `entry`, which is `rwm.<field>[<i>]` unless it was
+    /// built by one of the constructors in `config.lua`, which record where
+    /// they were called in `_loc`.
+    fn location(&self, entry: &Value, field: &str, i: usize) -> String {
+        if let Value::Table(t) = entry
+            && let Ok(Some(loc)) = t.get::<Option<String>>("_loc")
+        {
+            return loc;
+        }
+        format!("{}: rwm.{field}[{i}]", self.path)
+    }
+
+    /// Deserialize each entry of the sequence `rwm.<field>`, reporting any
+    /// that fail, and return the rest with their locations and raw values.
+    fn entries<T: DeserializeOwned>(
+        &mut self,
+        rwm: &Table,
+        field: &str,
+    ) -> Vec<(String, Value, T)> {
+        let table = match rwm.get::<Value>(field) {
+            Ok(Value::Table(t)) => t,
+            Ok(Value::Nil) => {
+                self.error(self.path, format!("missing rwm.{field}"));
+                return Vec::new();
+            }
+            Ok(v) => {
+                self.error(
+                    self.path,
+                    format!(
+                        "expected a table for rwm.{field}, found {}",
+                        v.type_name()
+                    ),
+                );
+                return Vec::new();
+            }
+            Err(e) => {
+                self.error(self.path, e.to_string());
+                return Vec::new();
+            }
+        };
+        let mut ret = Vec::new();
+        for (i, entry) in table.sequence_values::<Value>().enumerate() {
+            let i = i + 1;
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.error(
+                        &format!("{}: rwm.{field}[{i}]", self.path),
+                        e.to_string(),
+                    );
+                    continue;
+                }
+            };
+            let loc = self.location(&entry, field, i);
+            match deserialize(entry.clone()) {
+                Ok(v) => ret.push((loc, entry, v)),
+                Err(e) => self.error(&loc, e),
+            }
+        }
+        ret
+    }
+
+    /// Check that `arg` has the type expected by the handler `func` and that
+    /// any layout index or scratchpad name refers to something that exists.
+    fn arg(
+        &mut self,
+        loc: &str,
+        func: &str,
+        arg: &Arg,
+        nlayouts: usize,
+        scratchpads: &[String],
+    ) {
+        if let Arg::Name(name) = arg
+            && !scratchpads.contains(name)
+        {
+            self.error(loc, format!("no scratchpad named `{name}`"));
+        }
+        if let Arg::V(_) = arg
+            && func == "togglescratch"
+        {
+            self.warning(
+                loc,
+                "togglescratch with a command is deprecated, pass the \
+                 {name = ...} of a scratchpad instead",
+            );
+        }
+        if let Arg::L(Some(i)) = arg
+            && *i >= nlayouts
+        {
+            self.error(
+                loc,
+                format!(
+                    "layout index {i} is out of range for {nlayouts} layouts"
+                ),
+            );
+        }
+        let Some(kind) = arg_kind(func) else {
+            return;
+        };
+        if !kind.accepts(arg) {
+            self.error(
+                loc,
+                format!(
+                    "`{func}` expects an argument like {kind:?}, not {arg:?}"
+                ),
+            );
+        }
+    }
+
+    fn colors(&mut self, rwm: &Table) {
+        let loc = format!("{}: rwm.colors", self.path);
+        let colors = match rwm.get::<Value>("colors") {
+            Ok(Value::Table(t)) => t,
+            _ => {
+                self.error(&loc, "expected a table of color schemes");
+                return;
+            }
+        };
+        let mut schemes: Vec<(String, Value)> =
+            colors.pairs().filter_map(Result::ok).collect();
+        schemes.sort_by(|a, b| a.0.cmp(&b.0));
+        for required in ["norm", "sel"] {
+            if !schemes.iter().any(|(name, _)| name == required) {
+                self.error(&loc, format!("missing color scheme `{required}`"));
+            }
+        }
+        for (name, scheme) in schemes {
+            let loc = format!("{loc}.{name}");
+            if !SCHEMES.contains(&name.as_str()) {
+                self.warning(&loc, format!("unknown color scheme `{name}`"));
+            }
+            let Ok(scheme) = deserialize::<Vec<String>>(scheme) else {
+                self.error(&loc, "expected a list of color strings");
+                continue;
+            };
+            if scheme.len() != 3 {
+                self.error(
+                    &loc,
+                    format!(
+                        "expected 3 colors (foreground, background, border), \
+                         found {}",
+                        scheme.len()
+                    ),
+                );
+            }
+            for (i, color) in scheme.iter().enumerate() {
+                if !valid_color(color) {
+                    self.error(
+                        &format!("{loc}[{}]", i + 1),
+                        format!("invalid color `{color}`"),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The color schemes understood by [ColorMap](super::ColorMap).
+const SCHEMES: [&str; 8] = [
+    "norm",
+    "sel",
+    "hid",
+    "urg",
+    "title_norm",
+    "title_sel",
+    "tag_occ",
+    "status",
+];
+
+/// Whether `color` looks like something Xft can allocate. Hex colors are
+/// checked fully, while names are only checked for plausible characters
+/// since the color database lives in the X server.
+fn valid_color(color: &str) -> bool {
+    if let Some(hex) = color.strip_prefix('#') {
+        // 8 digits for #RRGGBBAA, the rest are understood by Xlib
+        return matches!(hex.len(), 3 | 6 | 8 | 9 | 12)
+            && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if color.starts_with("rgb:") || color.starts_with("rgbi:") {
+        return true;
+    }
+    !color.is_empty()
+        && color.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ')
+}
+
+fn deserialize<T: DeserializeOwned>(value: Value) -> Result<T, String> {
+    let de = mlua::serde::Deserializer::new(value);
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let path = e.path().to_string();
+        let msg = match e.into_inner() {
+            mlua::Error::DeserializeError(msg) => msg,
+            e => e.to_string(),
+        };
+        if path == "." { msg } else { format!("{path}: {msg}") }
+    })
+}
+
+/// Load the config file at `path` and report every problem found in it.
+pub fn check(path: impl AsRef<Path>) -> Vec<Diagnostic> {
+    let path = path.as_ref();
+    let display = path.display().to_string();
+    let mut checker = Checker { path: &display, diagnostics: Vec::new() };
+
+    let source = match read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            checker.error(&display, format!("failed to read config: {e}"));
+            return checker.diagnostics;
+        }
+    };
+    let builder = ConfigBuilder::new();
+    if let Err(e) = builder.exec(&source, path) {
+        checker.error(&display, e.to_string());
+        return checker.diagnostics;
+    }
+    let rwm = match builder.globals.get::<Value>("rwm") {
+        Ok(Value::Table(t)) => t,
+        _ => {
+            checker.error(&display, "`rwm` is not a table");
+            return checker.diagnostics;
+        }
+    };
+    if let Err(e) = crate::lua::install_api(&builder.lua, &rwm)
+        .and_then(|_| LuaRuntime::new(builder.lua.clone(), &rwm))
+    {
+        checker.error(&display, e.to_string());
+        return checker.diagnostics;
+    }
+
+    let layouts = checker.entries::<Layout>(&rwm, "layouts");
+    if layouts.is_empty() {
+        checker.error(&display, "no layouts defined");
+    }
+    let nlayouts = layouts.len();
+
+    let scratchpads: Vec<String> = checker
+        .entries::<Scratchpad>(&rwm, "scratchpads")
+        .into_iter()
+        .map(|(loc, _, sp)| {
+            if sp.class.is_empty() && sp.title.is_empty() {
+                checker.warning(
+                    &loc,
+                    format!(
+                        "scratchpad `{}` has no class or title, so it will \
+                         never find its window",
+                        sp.name
+                    ),
+                );
+            }
+            checker.placement(&loc, &sp.placement);
+            let Placement { w, h, .. } = sp.placement;
+            if w.into_iter().chain(h).any(|v| v <= 0.0) {
+                checker.error(
+                    &loc,
+                    format!("scratchpad `{}` has a size of zero", sp.name),
+                );
+            }
+            sp.name
+        })
+        .collect();
+
+    let keys = checker.entries::<Key>(&rwm, "keys");
+    let mut seen = HashMap::new();
+    for (loc, raw, key) in keys {
+        let func = handler_name(&raw);
+        checker.arg(&loc, &func, &key.arg, nlayouts, &scratchpads);
+        if let Some(first) = seen.insert((key.mod_, key.keysym), loc.clone()) {
+            checker.error(
+                &loc,
+                format!("key is already bound at {first}, both will run"),
+            );
+        }
+    }
+
+    let buttons = checker.entries::<Button>(&rwm, "buttons");
+    for (loc, raw, button) in buttons {
+        let func = handler_name(&raw);
+        // {I = 0} on the tag bar means to use the clicked tag instead
+        if button.click == crate::enums::Clk::TagBar as u32
+            && matches!(button.arg, Arg::I(0))
+        {
+            continue;
+        }
+        checker.arg(&loc, &func, &button.arg, nlayouts, &scratchpads);
+    }
+
+    for (loc, _, rule) in checker.entries::<Rule>(&rwm, "rules") {
+        checker.placement(&loc, &rule.placement);
+    }
+    checker.colors(&rwm);
+
+    // check everything else by deserializing a copy of the table with the
+    // fields checked above replaced by values known to be valid
+    let rest = || -> mlua::Result<Table> {
+        let lua = &builder.lua;
+        let rest = lua.create_table()?;
+        for pair in rwm.pairs::<Value, Value>() {
+            let (k, v) = pair?;
+            rest.raw_set(k, v)?;
+        }
+        for field in ["keys", "buttons", "rules", "layouts", "scratchpads"] {
+            rest.raw_set(field, lua.create_table()?)?;
+        }
+        let scheme = ["#000000", "#000000", "#000000"];
+        let colors = lua.create_table()?;
+        colors.set("norm", scheme)?;
+        colors.set("sel", scheme)?;
+        rest.raw_set("colors", colors)?;
+        Ok(rest)
+    };
+    match rest() {
+        Ok(rest) => {
+            if let Err(e) = deserialize::<Config>(Value::Table(rest)) {
+                checker.error(&display, format!("rwm.{e}"));
+            }
+        }
+        Err(e) => checker.error(&display, e.to_string()),
+    }
+
+    checker.diagnostics
+}
+
+/// The name of the handler in the `func` field of a key or button table.
+fn handler_name(raw: &Value) -> String {
+    match raw {
+        Value::Table(t) => t.get::<String>("func").unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostics(path: &str) -> Vec<String> {
+        check(path).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn clean() {
+        assert!(diagnostics("testfiles/config.lua").is_empty());
+    }
+
+    #[test]
+    fn problems() {
+        let path = "testfiles/check.lua";
+        assert_eq!(
+            diagnostics(path),
+            [
+                "testfiles/check.lua:3: error: func: unknown handler \
+                 `nosuchhandler`",
+                "testfiles/check.lua:4: error: `view` expects an argument like \
+                 Ui, not I(1)",
+                "testfiles/check.lua:5: error: layout index 99 is out of range \
+                 for 3 layouts",
+                "testfiles/check.lua:7: error: key is already bound at \
+                 testfiles/check.lua:6, both will run",
+                "testfiles/check.lua: rwm.keys[6]: error: `spawn` expects an \
+                 argument like V, not I(1)",
+                "testfiles/check.lua:10: warning: togglescratch with a command \
+                 is deprecated, pass the {name = ...} of a scratchpad instead",
+                "testfiles/check.lua:13: error: `x = 1` is a fraction that puts \
+                 the window at the edge of the monitor, use 0 or a number of \
+                 pixels greater than 1",
+            ]
+        );
+        let errors = check(path)
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count();
+        assert_eq!(errors, 6);
+    }
+
+    #[test]
+    fn missing_file() {
+        let got = diagnostics("testfiles/nonexistent.lua");
+        assert_eq!(got.len(), 1);
+        assert!(got[0].starts_with(
+            "testfiles/nonexistent.lua: error: failed to read config"
+        ));
+    }
+}
@@ -35,11 +35,13 @@ macro_rules! clicks {
     }
 }
 
-pub(super) static HANDLERS: [(&str, &str); 23] = handler_fns! {
+pub(super) static HANDLERS: [(&str, &str); 41] = handler_fns! {
     focusmon, focusstack, pushstack, incnmaster, killclient, quit, setlayout, setmfact,
     spawn, tag, tagmon, togglebar, togglefloating, toggletag, toggleview,
     view, zoom, movemouse, resizemouse, tile, monocle, fullscreen,
-    togglescratch,
+    togglescratch, reload, restart, incgaps, incigaps, incogaps, togglegaps,
+    bstack, bstackhoriz, centeredmaster, centeredfloatingmaster, grid, spiral,
+    dwindle, deck, setcfact, hidewin, showwin, showall,
 };
 
 pub(super) static CLICKS: [(&str, u32); 6] = clicks! {
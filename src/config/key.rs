@@ -14,8 +14,8 @@ impl TryFrom<String> for KeyFn {
         Ok(KeyFn(Some(
             FUNC_MAP
                 .get(value.as_str())
-                .cloned()
-                .ok_or_else(|| format!("no key `{value}`"))?,
+                .map(|&(f, _)| f)
+                .ok_or_else(|| format!("unknown handler `{value}`"))?,
         )))
     }
 }
@@ -23,6 +23,7 @@ impl TryFrom<String> for KeyFn {
 #[repr(C)]
 #[derive(Clone, serde::Deserialize)]
 pub struct Key {
+    #[serde(default, alias = "mod")]
     pub mod_: c_uint,
     pub keysym: KeySym,
     pub func: KeyFn,
@@ -54,32 +55,87 @@ impl Key {
 
 unsafe impl Sync for Key {}
 
-type FnMap = HashMap<&'static str, fn(&mut State, *const Arg)>;
+/// The handlers available to key and button bindings by name, along with the
+/// [Arg] variant each one expects.
+type FnMap = HashMap<&'static str, (fn(&mut State, *const Arg), ArgKind)>;
 pub(crate) static FUNC_MAP: LazyLock<FnMap> = LazyLock::new(|| {
     use crate::key_handlers::*;
+    use ArgKind::*;
     type FN = fn(&mut State, *const Arg);
     HashMap::from([
-        ("focusmon", focusmon as FN),
-        ("focusstack", focusstack as FN),
-        ("pushstack", pushstack as FN),
-        ("incnmaster", incnmaster as FN),
-        ("killclient", killclient as FN),
-        ("quit", quit as FN),
-        ("setlayout", setlayout as FN),
-        ("setmfact", setmfact as FN),
-        ("spawn", spawn as FN),
-        ("togglescratch", togglescratch as FN),
-        ("tag", tag as FN),
-        ("tagmon", tagmon as FN),
-        ("togglebar", togglebar as FN),
-        ("togglefloating", togglefloating as FN),
-        ("toggletag", toggletag as FN),
-        ("toggleview", toggleview as FN),
-        ("view", view as FN),
-        ("zoom", zoom as FN),
-        ("fullscreen", fullscreen as FN),
+        ("focusmon", (focusmon as FN, I)),
+        ("focusstack", (focusstack as FN, I)),
+        ("pushstack", (pushstack as FN, I)),
+        ("incnmaster", (incnmaster as FN, I)),
+        ("killclient", (killclient as FN, Unused)),
+        ("quit", (quit as FN, Unused)),
+        ("reload", (reload as FN, Unused)),
+        ("restart", (restart as FN, Unused)),
+        ("lua", (lua as FN, Lua)),
+        ("setlayout", (setlayout as FN, L)),
+        ("incgaps", (incgaps as FN, I)),
+        ("incigaps", (incigaps as FN, I)),
+        ("incogaps", (incogaps as FN, I)),
+        ("togglegaps", (togglegaps as FN, Unused)),
+        ("hidewin", (hidewin as FN, Unused)),
+        ("showwin", (showwin as FN, Unused)),
+        ("showall", (showall as FN, Unused)),
+        ("setmfact", (setmfact as FN, F)),
+        ("setcfact", (setcfact as FN, F)),
+        ("spawn", (spawn as FN, V)),
+        ("togglescratch", (togglescratch as FN, Name)),
+        ("tag", (tag as FN, Ui)),
+        ("tagmon", (tagmon as FN, I)),
+        ("togglebar", (togglebar as FN, Unused)),
+        ("togglefloating", (togglefloating as FN, Unused)),
+        ("toggletag", (toggletag as FN, Ui)),
+        ("toggleview", (toggleview as FN, Ui)),
+        ("view", (view as FN, Ui)),
+        ("zoom", (zoom as FN, Unused)),
+        ("fullscreen", (fullscreen as FN, Unused)),
         // mouse handlers
-        ("movemouse", movemouse as FN),
-        ("resizemouse", resizemouse as FN),
+        ("movemouse", (movemouse as FN, Unused)),
+        ("resizemouse", (resizemouse as FN, Unused)),
     ])
 });
+
+/// The [Arg] variant expected by a handler in [FUNC_MAP].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgKind {
+    /// The handler ignores its argument
+    Unused,
+    I,
+    Ui,
+    F,
+    V,
+    L,
+    Name,
+    /// A Lua function, which can only come from `config.lua`
+    Lua,
+}
+
+impl ArgKind {
+    /// Whether `arg` is the variant expected for this kind. Handlers that
+    /// ignore their argument accept any variant.
+    pub fn accepts(self, arg: &Arg) -> bool {
+        matches!(
+            (self, arg),
+            (ArgKind::Unused, _)
+                | (ArgKind::I, Arg::I(_))
+                | (ArgKind::Ui, Arg::Ui(_))
+                | (ArgKind::F, Arg::F(_))
+                | (ArgKind::V, Arg::V(_))
+                | (ArgKind::L, Arg::L(_))
+                | (ArgKind::Name, Arg::Name(_))
+                // the command of a scratchpad, from before they had names
+                | (ArgKind::Name, Arg::V(_))
+                | (ArgKind::Lua, Arg::Lua(_))
+        )
+    }
+}
+
+/// Look up the [ArgKind] for the handler named `func`, for building an [Arg]
+/// outside of `config.lua`, such as on the command line.
+pub fn arg_kind(func: &str) -> Option<ArgKind> {
+    FUNC_MAP.get(func).map(|&(_, kind)| kind)
+}
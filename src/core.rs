@@ -1,43 +1,50 @@
 use std::cmp::max;
-use std::ffi::{CStr, c_int, c_uint, c_ulong};
+use std::collections::HashMap;
+use std::ffi::{CStr, c_char, c_int, c_uint, c_ulong};
 use std::io::Read;
 use std::mem::{MaybeUninit, size_of};
-use std::ptr::null_mut;
+use std::path::PathBuf;
+use std::ptr::{addr_of_mut, null_mut};
 use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 
 use crate::config::Config;
 use crate::drw::{Drw, fontset_create};
-use crate::enums::{Clk, Col, Net, Scheme, WM, XEmbed};
-use crate::key_handlers::view;
-use crate::util::{self, ecalloc};
+use crate::enums::{Clk, Col, Net, Rwm, Scheme, WM, XEmbed};
+use crate::ipc::{self, Event, Ipc};
+use crate::key_handlers::{sendmon, view};
+use crate::layouts::geometry::Rect;
+use crate::lua;
+use crate::rules::{RuleActions, WindowProps, matchrules, rematchrules};
+use crate::status::{self, Segment};
+use crate::util::{self, die, ecalloc};
 use crate::xembed::{
     XEMBED_EMBEDDED_VERSION, XEMBED_MAPPED, XEMBED_WINDOW_ACTIVATE,
     XEMBED_WINDOW_DEACTIVATE,
 };
 use crate::{
-    Arg, Client, ICONIC_STATE, Layout, Monitor, NORMAL_STATE, Pertag, State,
-    Systray, WITHDRAWN_STATE, Window, drw, handlers, x,
+    Arg, BarTitle, Client, Gaps, ICONIC_STATE, Layout, Monitor, NORMAL_STATE,
+    Pertag, State, Systray, WITHDRAWN_STATE, Window, drw, handlers, x,
 };
 use libc::{c_long, c_uchar, pid_t, sigaction};
 use x11::keysym::XK_Num_Lock;
 use x11::xlib::{
     self, Above, AnyButton, AnyKey, AnyModifier, BadAccess, BadDrawable,
     BadMatch, BadWindow, Below, ButtonPressMask, ButtonReleaseMask,
-    CWBackPixel, CWBackPixmap, CWBorderWidth, CWCursor, CWEventMask, CWHeight,
-    CWOverrideRedirect, CWSibling, CWStackMode, CWWidth, CWX, CWY,
-    ClientMessage, ControlMask, CopyFromParent, CurrentTime, Display,
+    CWBackPixel, CWBorderPixel, CWBorderWidth, CWColormap, CWCursor,
+    CWEventMask, CWHeight, CWOverrideRedirect, CWSibling, CWStackMode, CWWidth,
+    CWX, CWY, ClientMessage, ControlMask, CopyFromParent, CurrentTime, Display,
     EnterWindowMask, ExposureMask, False, FocusChangeMask, GrabModeAsync,
     GrabModeSync, InputHint, IsViewable, LeaveWindowMask, LockMask, Mod1Mask,
     Mod2Mask, Mod3Mask, Mod4Mask, Mod5Mask, NoEventMask, PAspect, PBaseSize,
-    PMaxSize, PMinSize, PResizeInc, PSize, ParentRelative, PointerMotionMask,
-    PointerRoot, PropModeAppend, PropModeReplace, PropertyChangeMask,
-    RevertToPointerRoot, ShiftMask, StructureNotifyMask,
-    SubstructureNotifyMask, SubstructureRedirectMask, Success, True, XA_ATOM,
-    XA_CARDINAL, XA_STRING, XA_WINDOW, XA_WM_NAME, XChangeProperty,
-    XChangeWindowAttributes, XConfigureWindow, XCreateSimpleWindow,
-    XDestroyWindow, XErrorEvent, XFillRectangle, XFree, XGetSelectionOwner,
-    XInternAtom, XMapRaised, XMapSubwindows, XMapWindow, XMoveResizeWindow,
-    XPropertyEvent, XSelectInput, XSetErrorHandler, XSetForeground,
+    PMaxSize, PMinSize, PResizeInc, PSize, PointerMotionMask, PointerRoot,
+    PropModeAppend, PropModeReplace, PropertyChangeMask, RevertToPointerRoot,
+    ShiftMask, StructureNotifyMask, SubstructureNotifyMask,
+    SubstructureRedirectMask, Success, True, XA_ATOM, XA_CARDINAL, XA_STRING,
+    XA_WINDOW, XA_WM_NAME, XChangeProperty, XChangeWindowAttributes,
+    XConfigureWindow, XCreateSimpleWindow, XDestroyWindow, XErrorEvent, XFree,
+    XGetSelectionOwner, XInternAtom, XMapRaised, XMapSubwindows, XMapWindow,
+    XMoveResizeWindow, XPropertyEvent, XSelectInput, XSetErrorHandler,
     XSetSelectionOwner, XSetWindowAttributes, XSync, XUnmapWindow,
     XWindowChanges,
 };
@@ -155,6 +162,12 @@ pub fn createmon(state: &State) -> *mut Monitor {
         (*m).lt[0] = &state.config.layouts[0];
         (*m).lt[1] = &state.config.layouts[1 % state.config.layouts.len()];
         (*m).ltsymbol = state.config.layouts[0].symbol.clone();
+        (*m).titles = Vec::new();
+        (*m).gaps = Gaps {
+            inner: state.config.gappi as c_int,
+            outer: state.config.gappo as c_int,
+            enabled: true,
+        };
 
         (*m).pertag = Pertag {
             curtag: 1,
@@ -164,6 +177,7 @@ pub fn createmon(state: &State) -> *mut Monitor {
             sellts: vec![(*m).sellt; state.config.tags.len() + 1],
             ltidxs: vec![(*m).lt; state.config.tags.len() + 1],
             showbars: vec![(*m).showbar; state.config.tags.len() + 1],
+            gaps: vec![(*m).gaps; state.config.tags.len() + 1],
         };
     }
 
@@ -184,7 +198,7 @@ pub fn checkotherwm(dpy: *mut Display) {
     }
 }
 
-pub fn setup(dpy: *mut Display) -> State {
+pub fn setup(dpy: *mut Display, config_path: Option<PathBuf>) -> State {
     log::trace!("setup");
     unsafe {
         let mut wa = xlib::XSetWindowAttributes {
@@ -216,14 +230,42 @@ pub fn setup(dpy: *mut Display) -> State {
         libc::sigemptyset(&mut sa.sa_mask);
         libc::sigaction(libc::SIGCHLD, &sa, null_mut());
 
+        let mut fds = [-1; 2];
+        if libc::pipe(fds.as_mut_ptr()) == 0 {
+            for fd in fds {
+                libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            }
+            SIGNAL_PIPE[0].store(fds[0], Ordering::Relaxed);
+            SIGNAL_PIPE[1].store(fds[1], Ordering::Relaxed);
+        } else {
+            log::warn!("failed to create signal pipe, SIGHUP may be delayed");
+        }
+        // no SA_RESTART here so that SIGHUP interrupts the poll in `run`
+        sa.sa_sigaction = sighup as *const () as usize;
+        sa.sa_flags = 0;
+        libc::sigaction(libc::SIGHUP, &sa, null_mut());
+
         while libc::waitpid(-1, null_mut(), libc::WNOHANG) > 0 {}
 
         let screen = xlib::XDefaultScreen(dpy);
         let sh = xlib::XDisplayHeight(dpy, screen);
         let root = xlib::XRootWindow(dpy, screen);
         let sw = xlib::XDisplayWidth(dpy, screen);
-        let mut drw = drw::create(dpy, screen, root, sw as u32, sh as u32);
-        let config = Config::load_home();
+        let config = match &config_path {
+            Some(path) => Config::from_lua(path).unwrap_or_else(|e| {
+                die(&format!("rwm: failed to load {}: {e}", path.display()))
+            }),
+            None => Config::load_home(),
+        };
+        let mut drw = drw::create(
+            dpy,
+            screen,
+            root,
+            sw as u32,
+            sh as u32,
+            config.alphabar,
+        );
         if fontset_create(&mut drw, &config.fonts).is_err()
             || drw.fonts.is_empty()
         {
@@ -244,12 +286,14 @@ pub fn setup(dpy: *mut Display) -> State {
             wmatom: Default::default(),
             netatom: Default::default(),
             xatom: Default::default(),
+            rwmatom: Default::default(),
             dpy,
             lrpad: drw.fonts[0].h as i32,
             drw,
             selmon: null_mut(),
             mons: null_mut(),
             stext: String::new(),
+            statuscolors: HashMap::new(),
             scheme: Default::default(),
             screen,
             root,
@@ -258,8 +302,11 @@ pub fn setup(dpy: *mut Display) -> State {
             ),
             numlockmask: 0,
             running: true,
+            restart: false,
+            config_path,
             systray: None,
             config,
+            ipc: None,
 
             #[cfg(target_os = "linux")]
             xcon: null_mut(),
@@ -267,6 +314,16 @@ pub fn setup(dpy: *mut Display) -> State {
 
         updategeom(&mut state);
 
+        let display = CStr::from_ptr(xlib::XDisplayString(dpy));
+        state.ipc =
+            match Ipc::bind(ipc::socket_path(&display.to_string_lossy())) {
+                Ok(ipc) => Some(ipc),
+                Err(e) => {
+                    log::error!("failed to create IPC socket: {e}");
+                    None
+                }
+            };
+
         /* init atoms */
         let utf8string = XInternAtom(state.dpy, c"UTF8_STRING".as_ptr(), False);
         state.wmatom[WM::Protocols as usize] =
@@ -277,6 +334,8 @@ pub fn setup(dpy: *mut Display) -> State {
             XInternAtom(state.dpy, c"WM_STATE".as_ptr(), False);
         state.wmatom[WM::TakeFocus as usize] =
             XInternAtom(state.dpy, c"WM_TAKE_FOCUS".as_ptr(), False);
+        state.wmatom[WM::WindowRole as usize] =
+            XInternAtom(state.dpy, c"WM_WINDOW_ROLE".as_ptr(), False);
 
         state.netatom[Net::ActiveWindow as usize] =
             XInternAtom(state.dpy, c"_NET_ACTIVE_WINDOW".as_ptr(), False);
@@ -306,6 +365,8 @@ pub fn setup(dpy: *mut Display) -> State {
             XInternAtom(state.dpy, c"_NET_SUPPORTING_WM_CHECK".as_ptr(), False);
         state.netatom[Net::WMFullscreen as usize] =
             XInternAtom(state.dpy, c"_NET_WM_STATE_FULLSCREEN".as_ptr(), False);
+        state.netatom[Net::WMHidden as usize] =
+            XInternAtom(state.dpy, c"_NET_WM_STATE_HIDDEN".as_ptr(), False);
         state.netatom[Net::WMWindowType as usize] =
             XInternAtom(state.dpy, c"_NET_WM_WINDOW_TYPE".as_ptr(), False);
         state.netatom[Net::WMWindowTypeDialog as usize] = XInternAtom(
@@ -323,6 +384,17 @@ pub fn setup(dpy: *mut Display) -> State {
         state.xatom[XEmbed::XEmbedInfo as usize] =
             XInternAtom(state.dpy, c"_XEMBED_INFO".as_ptr(), False);
 
+        state.rwmatom[Rwm::Tags as usize] =
+            XInternAtom(state.dpy, c"_RWM_TAGS".as_ptr(), False);
+        state.rwmatom[Rwm::Floating as usize] =
+            XInternAtom(state.dpy, c"_RWM_FLOATING".as_ptr(), False);
+        state.rwmatom[Rwm::Geometry as usize] =
+            XInternAtom(state.dpy, c"_RWM_GEOMETRY".as_ptr(), False);
+        state.rwmatom[Rwm::Monitor as usize] =
+            XInternAtom(state.dpy, c"_RWM_MONITOR".as_ptr(), False);
+        state.rwmatom[Rwm::Hidden as usize] =
+            XInternAtom(state.dpy, c"_RWM_HIDDEN".as_ptr(), False);
+
         /* init appearance */
         for i in 0..state.config.colors.0.len() {
             state.scheme.push(drw::scm_create(
@@ -410,12 +482,164 @@ pub fn setup(dpy: *mut Display) -> State {
     }
 }
 
+/// Set by the `reload` handler and by SIGHUP to request a config reload from
+/// [run].
+pub static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// Read and write ends of a pipe that [sighup] writes to, so that a signal
+/// arriving after [run] checks [RELOAD] but before it blocks still wakes it up.
+static SIGNAL_PIPE: [AtomicI32; 2] = [AtomicI32::new(-1), AtomicI32::new(-1)];
+
+extern "C" fn sighup(_: c_int) {
+    RELOAD.store(true, Ordering::Relaxed);
+    let fd = SIGNAL_PIPE[1].load(Ordering::Relaxed);
+    if fd >= 0 {
+        // a full pipe already has a wakeup pending, so errors don't matter
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// Re-read the config file and apply it to the running window manager.
+///
+/// Managed clients and the per-tag settings of each monitor are kept, with
+/// layout pointers moved over to the matching entries in the new config by
+/// index. If the new config cannot be loaded, the current one stays in place.
+pub fn reload(state: &mut State) {
+    log::trace!("reload");
+    let Some(path) = state.config_path.clone().or_else(Config::home_path)
+    else {
+        log::error!("unable to determine config directory for reload");
+        return;
+    };
+    let config = match Config::from_lua(&path) {
+        Ok(c) if c.layouts.is_empty() => {
+            log::error!("failed to reload config: no layouts defined");
+            return;
+        }
+        Ok(c) => c,
+        Err(e) => {
+            log::error!("failed to reload config, keeping the old one: {e:?}");
+            return;
+        }
+    };
+    log::info!("reloading config from {}", path.display());
+
+    let old = std::mem::replace(&mut state.config, config);
+    let (ntags, tagmask) = (state.config.tags.len(), state.tagmask());
+    // drop tags that no longer exist and fall back to the first tag rather
+    // than leaving nothing visible
+    let retag = |tags: c_uint| {
+        let ret = tags & tagmask;
+        if ret == 0 { 1 } else { ret }
+    };
+    let scratchpads = &state.config.scratchpads;
+    let layouts = &state.config.layouts;
+    let relayout = |lt: *const Layout| -> *const Layout {
+        let i = old.layouts.iter().position(|l| std::ptr::eq(l, lt));
+        &layouts[i.unwrap_or(0).min(layouts.len() - 1)]
+    };
+    unsafe {
+        let mut m = state.mons;
+        while !m.is_null() {
+            let m_ = &mut *m;
+            m_.lt = m_.lt.map(relayout);
+            m_.tagset = m_.tagset.map(retag);
+            let pertag = &mut m_.pertag;
+            for lts in pertag.ltidxs.iter_mut() {
+                *lts = lts.map(relayout);
+            }
+            let default_lts =
+                [&layouts[0] as *const _, &layouts[1 % layouts.len()]];
+            pertag.nmasters.resize(ntags + 1, state.config.nmaster);
+            pertag.mfacts.resize(ntags + 1, state.config.mfact);
+            pertag.sellts.resize(ntags + 1, 0);
+            pertag.ltidxs.resize(ntags + 1, default_lts);
+            pertag.showbars.resize(ntags + 1, state.config.showbar);
+            pertag.gaps.resize(
+                ntags + 1,
+                Gaps {
+                    inner: state.config.gappi as c_int,
+                    outer: state.config.gappo as c_int,
+                    enabled: true,
+                },
+            );
+            if pertag.curtag > ntags {
+                pertag.curtag = 1;
+            }
+            if pertag.prevtag > ntags {
+                pertag.prevtag = 1;
+            }
+            let mut c = m_.clients;
+            while !c.is_null() {
+                // hidden scratchpads have no tags, unless their scratchpad was
+                // removed, in which case they're turned back into normal
+                // windows so they aren't lost
+                if let Some(name) = &(*c).scratchpad
+                    && !scratchpads.iter().any(|s| &s.name == name)
+                {
+                    (*c).scratchpad = None;
+                }
+                if (*c).scratchpad.is_none() || (*c).tags != 0 {
+                    (*c).tags = retag((*c).tags);
+                }
+                c = (*c).next;
+            }
+            m = m_.next;
+        }
+    }
+
+    let old_fonts = std::mem::take(&mut state.drw.fonts);
+    if fontset_create(&mut state.drw, &state.config.fonts).is_err()
+        || state.drw.fonts.is_empty()
+    {
+        log::error!("no fonts could be loaded, keeping the old ones");
+        state.drw.fonts = old_fonts;
+    } else {
+        drop(old_fonts);
+    }
+    state.bh = state.drw.fonts[0].h as i32 + 2;
+    state.lrpad = state.drw.fonts[0].h as i32;
+
+    for mut clr in std::mem::take(&mut state.scheme).into_colors() {
+        drw::clr_free(&state.drw, &mut clr);
+    }
+    for i in 0..state.config.colors.0.len() {
+        state.scheme.push(drw::scm_create(
+            &state.drw,
+            &state.config.colors.0[i],
+            3,
+        ));
+    }
+
+    grabkeys(state);
+    unsafe {
+        let mut m = state.mons;
+        while !m.is_null() {
+            updatebarpos(state, m);
+            resizebarwin(state, m);
+            let mut c = (*m).clients;
+            while !c.is_null() {
+                grabbuttons(state, c, c == (*state.selmon).sel);
+                let scheme =
+                    if (*c).isurgent { Scheme::Urg } else { Scheme::Norm };
+                let color = state.scheme[(scheme, Col::Border)].pixel;
+                xlib::XSetWindowBorder(state.dpy, (*c).win, color);
+                c = (*c).next;
+            }
+            m = (*m).next;
+        }
+        updatesystray(state);
+        focus(state, null_mut());
+    }
+    arrange(state, null_mut());
+}
+
 pub unsafe fn focus(state: &mut State, mut c: *mut Client) {
     log::trace!("focus: c = {c:?}");
     unsafe {
-        if c.is_null() || !is_visible(c) {
+        if c.is_null() || !can_focus(c) {
             c = (*state.selmon).stack;
-            while !c.is_null() && !is_visible(c) {
+            while !c.is_null() && !can_focus(c) {
                 c = (*c).snext;
             }
         }
@@ -450,6 +674,8 @@ pub unsafe fn focus(state: &mut State, mut c: *mut Client) {
         }
         (*state.selmon).sel = c;
         drawbars(state);
+        ipc::emit_focus(state, c);
+        lua::focus_hook(state, c);
     }
 }
 
@@ -616,7 +842,7 @@ pub fn arrangemon(state: &mut State, m: *mut Monitor) {
         (*m).ltsymbol = (*(*m).lt[(*m).sellt]).symbol.clone();
         let arrange = &(*(*m).lt[(*m).sellt]).arrange;
         if let Some(arrange) = arrange {
-            (arrange.0)(state, m);
+            arrange.arrange(state, m);
         }
     }
 }
@@ -1061,7 +1287,18 @@ pub fn updatenumlockmask(state: &mut State) {
 pub fn seturgent(state: &mut State, c: *mut Client, urg: bool) {
     log::trace!("seturgent");
     unsafe {
+        if (*c).isurgent != urg {
+            ipc::emit(state, Event::Urgent { window: (*c).win, urgent: urg });
+        }
+        let became_urgent = urg && !(*c).isurgent;
         (*c).isurgent = urg;
+        if became_urgent {
+            let color = state.scheme[(Scheme::Urg, Col::Border)].pixel;
+            xlib::XSetWindowBorder(state.dpy, (*c).win, color);
+        }
+        if became_urgent {
+            lua::client_hook(state, "urgent", c);
+        }
         let wmh = xlib::XGetWMHints(state.dpy, (*c).win);
         if wmh.is_null() {
             return;
@@ -1083,8 +1320,8 @@ pub fn unfocus(state: &mut State, c: *mut Client, setfocus: bool) {
     }
     grabbuttons(state, c, false);
     unsafe {
-        // scheme[SchemeNorm][ColBorder].pixel
-        let color = state.scheme[(Scheme::Norm, Col::Border)].pixel;
+        let scheme = if (*c).isurgent { Scheme::Urg } else { Scheme::Norm };
+        let color = state.scheme[(scheme, Col::Border)].pixel;
         xlib::XSetWindowBorder(state.dpy, (*c).win, color);
         if setfocus {
             xlib::XSetInputFocus(
@@ -1107,6 +1344,7 @@ pub fn updatestatus(state: &mut State) {
     if gettextprop(state.dpy, state.root, XA_WM_NAME, &mut state.stext) == 0 {
         state.stext = "rwm-1.0".to_string();
     }
+    updatestatuscolors(state);
     drawbar(state, state.selmon);
     updatesystray(state);
 }
@@ -1332,21 +1570,14 @@ pub fn updatesystray(state: &mut State) {
         );
         XMapWindow(state.dpy, state.systray().win);
         XMapSubwindows(state.dpy, state.systray().win);
-        // redraw background
-        XSetForeground(
-            state.dpy,
-            state.drw.gc,
-            state.scheme[(Scheme::Norm, Col::Bg)].pixel,
-        );
-        XFillRectangle(
+        // redraw background. the systray uses the default visual, so it
+        // can't share the GC of the bar when that has an ARGB visual
+        xlib::XSetWindowBackground(
             state.dpy,
             state.systray().win,
-            state.drw.gc,
-            0,
-            0,
-            w,
-            state.bh as u32,
+            state.scheme[(Scheme::Norm, Col::Bg)].pixel,
         );
+        xlib::XClearWindow(state.dpy, state.systray().win);
         XSync(state.dpy, False);
     } // end unsafe
 }
@@ -1397,7 +1628,141 @@ pub fn systraytomon(state: &State, m: *mut Monitor) -> *mut Monitor {
 
 pub fn textw(drw: &mut Drw, x: &str, lrpad: c_int) -> c_int {
     log::trace!("textw");
-    unsafe { drw::fontset_getwidth(drw, x) as c_int + lrpad }
+    let x = status::strip(x);
+    unsafe { drw::fontset_getwidth(drw, &x) as c_int + lrpad }
+}
+
+/// Allocate the colors used by the escapes in the status text, keeping the
+/// ones that are still used and freeing the rest.
+fn updatestatuscolors(state: &mut State) {
+    let mut old = std::mem::take(&mut state.statuscolors);
+    for segment in status::parse(&state.stext) {
+        let (Segment::Fg(Some(name)) | Segment::Bg(Some(name))) = segment
+        else {
+            continue;
+        };
+        if state.statuscolors.contains_key(name) {
+            continue;
+        }
+        let clr = match old.remove(name) {
+            Some(clr) => clr,
+            None => match drw::clr_try_create(&state.drw, name) {
+                Some(clr) => clr,
+                None => {
+                    log::warn!("invalid color `{name}` in status text");
+                    continue;
+                }
+            },
+        };
+        state.statuscolors.insert(name.to_owned(), clr);
+    }
+    for mut clr in old.into_values() {
+        drw::clr_free(&state.drw, &mut clr);
+    }
+}
+
+/// Draw the status text in the `w` pixels from `x` on the bar, interpreting
+/// the escapes described in [status].
+fn drawstatus(state: &mut State, x: c_int, w: c_int, lpad: c_int) {
+    let norm = state.scheme[Scheme::Status].clone();
+    drw::setscheme(&mut state.drw, norm.clone());
+    drw::rect(&mut state.drw, x, 0, w as u32, state.bh as u32, true, true);
+    let end = x + w;
+    let mut x = x + lpad;
+    let text = state.stext.clone();
+    for segment in status::parse(&text) {
+        match segment {
+            Segment::Text(t) => {
+                let tw = textw(&mut state.drw, t, 0).min(end - x);
+                if tw > 0 {
+                    unsafe {
+                        drw::text(
+                            &mut state.drw,
+                            x,
+                            0,
+                            tw as u32,
+                            state.bh as u32,
+                            0,
+                            t,
+                            0,
+                        );
+                    }
+                    x += tw;
+                }
+            }
+            Segment::Fg(c) | Segment::Bg(c) => {
+                let col = if matches!(segment, Segment::Fg(_)) {
+                    Col::Fg
+                } else {
+                    Col::Bg
+                } as usize;
+                let clr = match c {
+                    Some(c) => state.statuscolors.get(c).copied(),
+                    None => Some(norm[col]),
+                };
+                if let Some(clr) = clr {
+                    state.drw.scheme[col] = clr;
+                }
+            }
+            Segment::Rect { x: rx, y, w, h } => {
+                let rx = x + rx;
+                let w = w.min(end - rx);
+                if w > 0 && h > 0 {
+                    drw::rect(
+                        &mut state.drw,
+                        rx,
+                        y,
+                        w as u32,
+                        h as u32,
+                        true,
+                        false,
+                    );
+                }
+            }
+            Segment::Reset => drw::setscheme(&mut state.drw, norm.clone()),
+            Segment::Signal(_) => {}
+        }
+    }
+}
+
+/// Forward a click with `button` on the status text, `x` pixels from the left
+/// of the bar, to the status program if it landed on a block marked with
+/// `^sig(n)`. Returns whether the click was forwarded, which is never the case
+/// outside of Linux.
+pub fn sigstatus(state: &mut State, x: c_int, button: c_uint) -> bool {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        let stw = getsystraywidth(state) as c_int;
+        let tw = textw(&mut state.drw, &state.stext, state.lrpad / 2) + 2;
+        let start = (*state.selmon).ww - stw - tw + state.lrpad / 2 - 2;
+        let text = state.stext.clone();
+        let Some(sig) = status::signal_at(&text, x - start, |t| {
+            textw(&mut state.drw, t, 0)
+        }) else {
+            return false;
+        };
+        let Some(pid) = util::pidof(&state.config.statusprogram) else {
+            log::warn!("{} is not running", state.config.statusprogram);
+            return false;
+        };
+        // pass the button along with the signal for the status program to
+        // set BLOCK_BUTTON
+        let value = libc::sigval { sival_ptr: button as usize as *mut _ };
+        if libc::sigqueue(pid, libc::SIGRTMIN() + sig, value) != 0 {
+            log::warn!(
+                "failed to signal {}: {}",
+                state.config.statusprogram,
+                std::io::Error::last_os_error()
+            );
+        }
+        true
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (state, x, button);
+        false
+    }
 }
 
 pub fn drawbar(state: &mut State, m: *mut Monitor) {
@@ -1423,18 +1788,13 @@ pub fn drawbar(state: &mut State, m: *mut Monitor) {
         // draw status first so it can be overdrawn by tags later
         if m == state.selmon {
             // status is only drawn on selected monitor
-            drw::setscheme(&mut state.drw, state.scheme[Scheme::Norm].clone());
             tw = textw(&mut state.drw, &state.stext, state.lrpad / 2) + 2; // 2px right padding
             log::trace!("drawbar: text");
-            drw::text(
-                &mut state.drw,
+            drawstatus(
+                state,
                 (*m).ww - tw - stw as i32,
-                0,
-                tw as u32,
-                state.bh as u32,
-                (state.lrpad / 2 - 2) as u32,
-                &state.stext,
-                0,
+                tw,
+                state.lrpad / 2 - 2,
             );
         }
 
@@ -1453,15 +1813,16 @@ pub fn drawbar(state: &mut State, m: *mut Monitor) {
         for (i, tag) in state.config.tags.iter().enumerate() {
             let text = tag.to_owned();
             let w = textw(&mut state.drw, &text, state.lrpad);
-            drw::setscheme(
-                &mut state.drw,
-                state.scheme[if ((*m).tagset[(*m).seltags] & (1 << i)) != 0 {
-                    Scheme::Sel
-                } else {
-                    Scheme::Norm
-                }]
-                .clone(),
-            );
+            let scheme = if urg & (1 << i) != 0 {
+                Scheme::Urg
+            } else if (*m).tagset[(*m).seltags] & (1 << i) != 0 {
+                Scheme::Sel
+            } else if occ & (1 << i) != 0 {
+                Scheme::TagOcc
+            } else {
+                Scheme::Norm
+            };
+            drw::setscheme(&mut state.drw, state.scheme[scheme].clone());
             log::trace!("drawbar: text 2");
             drw::text(
                 &mut state.drw,
@@ -1471,7 +1832,7 @@ pub fn drawbar(state: &mut State, m: *mut Monitor) {
                 state.bh as u32,
                 state.lrpad as u32 / 2,
                 &text,
-                (urg as i32) & (1 << i),
+                0,
             );
 
             if (occ & (1 << i)) != 0 {
@@ -1484,7 +1845,7 @@ pub fn drawbar(state: &mut State, m: *mut Monitor) {
                     m == state.selmon
                         && !(*state.selmon).sel.is_null()
                         && ((*(*state.selmon).sel).tags & (1 << i)) != 0,
-                    (urg & (1 << i)) != 0,
+                    false,
                 );
             }
             x += w as i32;
@@ -1506,43 +1867,21 @@ pub fn drawbar(state: &mut State, m: *mut Monitor) {
         log::trace!("finished drawbar text 3");
 
         let w = (*m).ww - tw - stw as i32 - x;
+        (*m).titles.clear();
         if w > state.bh {
-            if !(*m).sel.is_null() {
-                drw::setscheme(
-                    &mut state.drw,
-                    state.scheme[if m == state.selmon {
-                        Scheme::Sel
-                    } else {
-                        Scheme::Norm
-                    }]
-                    .clone(),
-                );
-                log::trace!("drawbar: text 4");
-                drw::text(
-                    &mut state.drw,
-                    x,
-                    0,
-                    w as u32,
-                    state.bh as u32,
-                    state.lrpad as u32 / 2,
-                    &(*(*m).sel).name,
-                    0,
-                );
-                if (*(*m).sel).isfloating {
-                    drw::rect(
-                        &mut state.drw,
-                        x + boxs as i32,
-                        boxs as i32,
-                        boxw,
-                        boxw,
-                        (*(*m).sel).isfixed,
-                        false,
-                    );
+            // split the title area among every client on the selected tags,
+            // including hidden ones so that they can be clicked to restore them
+            let mut clients = Vec::new();
+            let mut c;
+            cfor!((c = (*m).clients; !c.is_null(); c = (*c).next) {
+                if on_seltags(c) {
+                    clients.push(c);
                 }
-            } else {
+            });
+            if clients.is_empty() {
                 drw::setscheme(
                     &mut state.drw,
-                    state.scheme[Scheme::Norm].clone(),
+                    state.scheme[Scheme::TitleNorm].clone(),
                 );
                 drw::rect(
                     &mut state.drw,
@@ -1554,6 +1893,22 @@ pub fn drawbar(state: &mut State, m: *mut Monitor) {
                     true,
                 );
             }
+            let n = clients.len() as c_int;
+            let end = x + w;
+            let mut x = x;
+            for (i, c) in clients.into_iter().enumerate() {
+                // the last entry takes up any remainder from the division
+                let ew = if i as c_int == n - 1 { end - x } else { w / n };
+                let scheme = if c == (*m).sel && m == state.selmon {
+                    Scheme::TitleSel
+                } else if (*c).ishidden {
+                    Scheme::Hid
+                } else {
+                    Scheme::TitleNorm
+                };
+                drawtitle(state, m, c, x, ew, scheme);
+                x += ew;
+            }
         }
         drw::map(
             &state.drw,
@@ -1566,6 +1921,45 @@ pub fn drawbar(state: &mut State, m: *mut Monitor) {
     }
 }
 
+/// Draw the title of `c` in the bar of `m` and record it in `m.titles` so
+/// that it can be clicked.
+fn drawtitle(
+    state: &mut State,
+    m: *mut Monitor,
+    c: *mut Client,
+    x: c_int,
+    w: c_int,
+    scheme: Scheme,
+) {
+    unsafe {
+        let boxs = state.drw.fonts[0].h / 9;
+        let boxw = state.drw.fonts[0].h / 6 + 2;
+        drw::setscheme(&mut state.drw, state.scheme[scheme].clone());
+        drw::text(
+            &mut state.drw,
+            x,
+            0,
+            w as u32,
+            state.bh as u32,
+            state.lrpad as u32 / 2,
+            &(*c).name,
+            0,
+        );
+        if (*c).isfloating {
+            drw::rect(
+                &mut state.drw,
+                x + boxs as i32,
+                boxs as i32,
+                boxw,
+                boxw,
+                (*c).isfixed,
+                false,
+            );
+        }
+        (*m).titles.push(BarTitle { x, w, client: c });
+    }
+}
+
 pub fn gettextprop(
     dpy: *mut Display,
     w: Window,
@@ -1630,7 +2024,9 @@ pub fn updatebars(state: &mut State) {
     log::trace!("updatebars");
     let mut wa = xlib::XSetWindowAttributes {
         override_redirect: True,
-        background_pixmap: ParentRelative as u64,
+        // a ParentRelative background only works for windows with the same
+        // depth as the root, so use a background pixel instead
+        background_pixmap: 0,
         event_mask: ButtonPressMask | ExposureMask,
         // everything else should be uninit I guess
         background_pixel: 0,
@@ -1643,7 +2039,7 @@ pub fn updatebars(state: &mut State) {
         backing_pixel: 0,
         save_under: 0,
         do_not_propagate_mask: 0,
-        colormap: 0,
+        colormap: state.drw.cmap,
         cursor: 0,
     };
     let mut ch = xlib::XClassHint {
@@ -1669,10 +2065,14 @@ pub fn updatebars(state: &mut State) {
                 w as c_uint,
                 state.bh as c_uint,
                 0,
-                xlib::XDefaultDepth(state.dpy, state.screen),
+                state.drw.depth as c_int,
                 CopyFromParent as c_uint,
-                xlib::XDefaultVisual(state.dpy, state.screen),
-                CWOverrideRedirect | CWBackPixmap | CWEventMask,
+                state.drw.visual,
+                CWOverrideRedirect
+                    | CWBackPixel
+                    | CWBorderPixel
+                    | CWColormap
+                    | CWEventMask,
                 &mut wa,
             );
             xlib::XDefineCursor(
@@ -1827,6 +2227,7 @@ pub fn updategeom(state: &mut State) -> i32 {
         if dirty != 0 {
             state.selmon = state.mons;
             state.selmon = wintomon(state, state.root);
+            ipc::emit_monitors(state);
         }
         dirty
     }
@@ -1910,7 +2311,7 @@ pub fn removesystrayicon(state: &mut State, i: *mut Client) {
         if !ii.is_null() {
             *ii = (*i).next;
         }
-        libc::free(i.cast());
+        freeclient(i);
     }
 }
 
@@ -2022,9 +2423,44 @@ pub fn detachstack(c: *mut Client) {
 /// as close as I can get
 #[inline]
 pub fn is_visible(c: *const Client) -> bool {
+    unsafe { !(*c).ishidden && on_seltags(c) }
+}
+
+/// Whether `c` is visible and not kept from being focused by a rule.
+#[inline]
+pub fn can_focus(c: *const Client) -> bool {
+    unsafe { is_visible(c) && !(*c).nofocus }
+}
+
+/// Whether `c` is on the selected tags of its monitor, even if it is hidden.
+#[inline]
+pub fn on_seltags(c: *const Client) -> bool {
     unsafe { ((*c).tags & (*(*c).mon).tagset[(*(*c).mon).seltags]) != 0 }
 }
 
+/// Hide `c` without unmapping it or changing its tags, leaving it on the bar.
+pub fn hide(state: &mut State, c: *mut Client) {
+    unsafe {
+        if c.is_null() || (*c).ishidden {
+            return;
+        }
+        setclientstate(state, c, ICONIC_STATE);
+        focus(state, null_mut());
+        arrange(state, (*c).mon);
+    }
+}
+
+/// Restore `c` after [hide].
+pub fn show(state: &mut State, c: *mut Client) {
+    unsafe {
+        if c.is_null() || !(*c).ishidden {
+            return;
+        }
+        setclientstate(state, c, NORMAL_STATE);
+        arrange(state, (*c).mon);
+    }
+}
+
 pub fn updatebarpos(state: &mut State, m: *mut Monitor) {
     log::trace!("updatebarpos");
 
@@ -2067,6 +2503,10 @@ pub fn isuniquegeom(
 pub fn cleanup(mut state: State) {
     log::trace!("entering cleanup");
 
+    if state.restart {
+        saveclients(&mut state);
+    }
+
     unsafe {
         let a = Arg::Ui(!0);
         view(&mut state, &a);
@@ -2106,6 +2546,10 @@ pub fn cleanup(mut state: State) {
             state.netatom[Net::ActiveWindow as usize],
         );
 
+        for mut clr in std::mem::take(&mut state.statuscolors).into_values() {
+            drw::clr_free(&state.drw, &mut clr);
+        }
+
         #[cfg(target_os = "linux")]
         drop(Box::from_raw(state.xcon));
 
@@ -2118,6 +2562,8 @@ pub fn cleanup(mut state: State) {
 pub fn unmanage(state: &mut State, c: *mut Client, destroyed: c_int) {
     log::trace!("unmanage");
     unsafe {
+        ipc::emit(state, Event::Unmanage { window: (*c).win });
+        lua::client_hook(state, "unmanage", c);
         let m = (*c).mon;
         let mut wc = xlib::XWindowChanges {
             x: 0,
@@ -2136,7 +2582,7 @@ pub fn unmanage(state: &mut State, c: *mut Client, destroyed: c_int) {
 
         let s = swallowingclient(state, (*c).win);
         if !s.is_null() {
-            libc::free((*s).swallowing.cast());
+            freeclient((*s).swallowing);
             (*s).swallowing = null_mut();
             arrange(state, m);
             focus(state, null_mut());
@@ -2167,7 +2613,7 @@ pub fn unmanage(state: &mut State, c: *mut Client, destroyed: c_int) {
             xlib::XSetErrorHandler(Some(xerror));
             xlib::XUngrabServer(state.dpy);
         }
-        libc::free(c.cast());
+        freeclient(c);
 
         if s.is_null() {
             arrange(state, m);
@@ -2343,6 +2789,8 @@ pub fn updateclientlist(state: &mut State) {
     }
 }
 
+/// Set the ICCCM `WM_STATE` of `c`. Moving into or out of `IconicState` also
+/// hides or shows `c` and updates its `_NET_WM_STATE` to match.
 pub fn setclientstate(s: &mut State, c: *mut Client, state: usize) {
     let mut data: [c_long; 2] = [state as c_long, XNONE as c_long];
     let ptr: *mut c_uchar = data.as_mut_ptr().cast();
@@ -2357,6 +2805,34 @@ pub fn setclientstate(s: &mut State, c: *mut Client, state: usize) {
             ptr,
             2,
         );
+        let hidden = state == ICONIC_STATE;
+        if (*c).ishidden != hidden {
+            (*c).ishidden = hidden;
+            updatenetwmstate(s, c);
+        }
+    }
+}
+
+/// Set the `_NET_WM_STATE` of `c` from its fullscreen and hidden flags.
+pub fn updatenetwmstate(state: &mut State, c: *mut Client) {
+    unsafe {
+        let mut atoms = Vec::with_capacity(2);
+        if (*c).isfullscreen {
+            atoms.push(state.netatom[Net::WMFullscreen as usize]);
+        }
+        if (*c).ishidden {
+            atoms.push(state.netatom[Net::WMHidden as usize]);
+        }
+        xlib::XChangeProperty(
+            state.dpy,
+            (*c).win,
+            state.netatom[Net::WMState as usize],
+            XA_ATOM,
+            32,
+            PropModeReplace,
+            atoms.as_ptr().cast(),
+            atoms.len() as c_int,
+        );
     }
 }
 
@@ -2389,12 +2865,33 @@ pub static HANDLER: LazyLock<[HandlerFn; x11::xlib::LASTEvent as usize]> =
 pub fn run(state: &mut State) {
     unsafe {
         xlib::XSync(state.dpy, False);
+        let xfd = xlib::XConnectionNumber(state.dpy);
+        let sigfd = SIGNAL_PIPE[0].load(Ordering::Relaxed);
         let mut ev: MaybeUninit<xlib::XEvent> = MaybeUninit::uninit();
-        while state.running && xlib::XNextEvent(state.dpy, ev.as_mut_ptr()) == 0
-        {
-            let mut ev: xlib::XEvent = ev.assume_init();
-            if let Some(handler) = HANDLER.get(ev.type_ as usize) {
-                handler(state, &mut ev);
+        while state.running {
+            // drain the X event queue before blocking on the X connection and
+            // the IPC sockets together
+            while state.running && xlib::XPending(state.dpy) > 0 {
+                xlib::XNextEvent(state.dpy, ev.as_mut_ptr());
+                let mut ev: xlib::XEvent = ev.assume_init();
+                if let Some(handler) = HANDLER.get(ev.type_ as usize) {
+                    handler(state, &mut ev);
+                }
+            }
+            // reloading is deferred to here because handlers run with
+            // pointers into the current config, such as their own Arg
+            if RELOAD.swap(false, Ordering::Relaxed) {
+                reload(state);
+                continue;
+            }
+            if state.running {
+                ipc::wait(state, &[xfd, sigfd]);
+                // empty the signal pipe, RELOAD says whether anything was in it
+                let mut buf = [0u8; 64];
+                while sigfd >= 0
+                    && libc::read(sigfd, buf.as_mut_ptr().cast(), buf.len()) > 0
+                {
+                }
             }
         }
     }
@@ -2467,12 +2964,39 @@ pub fn scan(state: &mut State) {
     }
 }
 
+/// Allocate a zeroed client. The fields that own memory are not valid when
+/// zeroed, so they are written without dropping the old value. Free the client
+/// with [freeclient].
+pub fn newclient() -> *mut Client {
+    unsafe {
+        let c: *mut Client = ecalloc(1, size_of::<Client>()).cast();
+        addr_of_mut!((*c).name).write(String::new());
+        addr_of_mut!((*c).class).write(String::new());
+        addr_of_mut!((*c).instance).write(String::new());
+        addr_of_mut!((*c).scratchpad).write(None);
+        addr_of_mut!((*c).reapplied).write(Vec::new());
+        c
+    }
+}
+
+/// Drop the owned fields of a client allocated by [newclient] and free it.
+pub fn freeclient(c: *mut Client) {
+    unsafe {
+        std::ptr::drop_in_place(addr_of_mut!((*c).name));
+        std::ptr::drop_in_place(addr_of_mut!((*c).class));
+        std::ptr::drop_in_place(addr_of_mut!((*c).instance));
+        std::ptr::drop_in_place(addr_of_mut!((*c).scratchpad));
+        std::ptr::drop_in_place(addr_of_mut!((*c).reapplied));
+        libc::free(c.cast());
+    }
+}
+
 pub fn manage(state: &mut State, w: Window, wa: *mut xlib::XWindowAttributes) {
     log::trace!("manage");
     let mut trans = 0;
     unsafe {
         let wa = *wa;
-        let c: *mut Client = util::ecalloc(1, size_of::<Client>()) as *mut _;
+        let c = newclient();
         (*c).win = w;
         (*c).pid = winpid(state, w);
         (*c).x = wa.x;
@@ -2484,11 +3008,15 @@ pub fn manage(state: &mut State, w: Window, wa: *mut xlib::XWindowAttributes) {
         (*c).h = wa.height;
         (*c).oldh = wa.height;
         (*c).oldbw = wa.border_width;
-        (*c).name = String::new();
+        (*c).cfact = 1.0;
+
+        (*c).bw = state.config.borderpx as i32;
 
         let mut term: *mut Client = null_mut();
+        let mut actions = RuleActions::default();
 
         updatetitle(state, c);
+        updateclass(state, c);
         log::trace!("manage: XGetTransientForHint");
         if xlib::XGetTransientForHint(state.dpy, w, &mut trans) != 0 {
             let t = wintoclient(state, trans);
@@ -2498,16 +3026,17 @@ pub fn manage(state: &mut State, w: Window, wa: *mut xlib::XWindowAttributes) {
             } else {
                 // NOTE must keep in sync with else below
                 (*c).mon = state.selmon;
-                applyrules(state, c);
+                actions = applyrules(state, c);
                 term = termforwin(state, c);
             }
         } else {
             // copied else case from above because the condition is supposed
             // to be xgettransientforhint && (t = wintoclient)
             (*c).mon = state.selmon;
-            applyrules(state, c);
+            actions = applyrules(state, c);
             term = termforwin(state, c);
         }
+        let restored = restoreclient(state, c);
         if (*c).x + width(c) > ((*(*c).mon).wx + (*(*c).mon).ww) as i32 {
             (*c).x = ((*(*c).mon).wx + (*(*c).mon).ww) as i32 - width(c);
         }
@@ -2516,22 +3045,18 @@ pub fn manage(state: &mut State, w: Window, wa: *mut xlib::XWindowAttributes) {
         }
         (*c).x = max((*c).x, (*(*c).mon).wx as i32);
         (*c).y = max((*c).y, (*(*c).mon).wy as i32);
-        (*c).bw = state.config.borderpx as i32;
 
-        // TODO pretty sure this doesn't work with pertags, which explains some
-        // behavior I saw before in dwm. probably need to operate on
-        // selmon.pertag.tags[selmon.pertag.curtag].
-        //
-        // TODO I'm also pretty sure this is _not_ the right way to be handling
-        // this. checking the name of the window and applying these rules seems
-        // like something meant to be handled by RULES
-        (*state.selmon).tagset[(*state.selmon).seltags] &= !state.scratchtag();
-        if (*c).name == state.config.scratchpadname {
-            (*c).tags = state.scratchtag();
-            (*(*c).mon).tagset[(*(*c).mon).seltags] |= (*c).tags;
-            (*c).isfloating = true;
-            (*c).x = (*(*c).mon).wx + (*(*c).mon).ww / 2 - width(c) / 2;
-            (*c).y = (*(*c).mon).wy + (*(*c).mon).wh / 2 - height(c) / 2;
+        // place newly spawned scratchpads, and floating windows whose rules
+        // say where. Tiled windows keep their own geometry for when they float
+        let placement = actions
+            .placement
+            .filter(|_| (*c).isfloating)
+            .map(|p| p.place(windowarea((*c).mon), (*c).w, (*c).h, (*c).bw));
+        if !restored && let Some(r) = scratchgeom(state, c).or(placement) {
+            (*c).x = r.x;
+            (*c).y = r.y;
+            (*c).w = r.w;
+            (*c).h = r.h;
         }
 
         log::trace!("manage: XWindowChanges");
@@ -2560,6 +3085,9 @@ pub fn manage(state: &mut State, w: Window, wa: *mut xlib::XWindowAttributes) {
         xlib::XSetWindowBorder(state.dpy, w, pixel);
         configure(state, c); // propagates border width, if size doesn't change
         updatewindowtype(state, c);
+        if actions.isfullscreen {
+            setfullscreen(state, c, true);
+        }
         updatesizehints(state, c);
         updatewmhints(state, c);
         xlib::XSelectInput(
@@ -2599,17 +3127,39 @@ pub fn manage(state: &mut State, w: Window, wa: *mut xlib::XWindowAttributes) {
             (*c).w as u32,
             (*c).h as u32,
         );
-        setclientstate(state, c, NORMAL_STATE);
-        if (*c).mon == state.selmon {
-            unfocus(state, (*state.selmon).sel, false);
+        // windows that were iconic before we managed them, or hidden before a
+        // restart, start out hidden
+        if (*c).ishidden || getstate(state, w) == ICONIC_STATE as c_long {
+            setclientstate(state, c, ICONIC_STATE);
+        } else {
+            setclientstate(state, c, NORMAL_STATE);
+            if !(*c).nofocus {
+                if (*c).mon == state.selmon {
+                    unfocus(state, (*state.selmon).sel, false);
+                }
+                (*(*c).mon).sel = c;
+            }
         }
-        (*(*c).mon).sel = c;
         arrange(state, (*c).mon);
         xlib::XMapWindow(state.dpy, (*c).win);
         if !term.is_null() {
             swallow(state, term, c);
         }
+        ipc::emit(state, Event::Manage { client: ipc::client_info(c) });
         focus(state, std::ptr::null_mut());
+        // hooks run once the client is mapped and focused, so that managing
+        // it doesn't override what they do with rwm.settags and the like
+        lua::client_hook(state, "manage", c);
+        if (*c).isurgent {
+            lua::client_hook(state, "urgent", c);
+        }
+        if actions.switchtotag && !(*c).ishidden && !on_seltags(c) {
+            if (*c).mon != state.selmon {
+                unfocus(state, (*state.selmon).sel, true);
+                state.selmon = (*c).mon;
+            }
+            view(state, &Arg::Ui((*c).tags));
+        }
     }
 }
 
@@ -2623,7 +3173,27 @@ pub fn updatewmhints(state: &mut State, c: *mut Client) {
                 (*wmh).flags &= !URGENT;
                 xlib::XSetWMHints(state.dpy, (*c).win, wmh);
             } else {
-                (*c).isurgent = ((*wmh).flags & URGENT != 0) as bool;
+                let urgent = (*wmh).flags & URGENT != 0;
+                let changed = (*c).isurgent != urgent;
+                if changed {
+                    ipc::emit(
+                        state,
+                        Event::Urgent { window: (*c).win, urgent },
+                    );
+                }
+                (*c).isurgent = urgent;
+                if changed {
+                    let scheme =
+                        if urgent { Scheme::Urg } else { Scheme::Norm };
+                    let color = state.scheme[(scheme, Col::Border)].pixel;
+                    xlib::XSetWindowBorder(state.dpy, (*c).win, color);
+                }
+                // manage runs the hook itself once the client is attached,
+                // since the hook can't do anything with it before then
+                if changed && urgent && !wintoclient(state, (*c).win).is_null()
+                {
+                    lua::client_hook(state, "urgent", c);
+                }
             }
             if (*wmh).flags & InputHint != 0 {
                 (*c).neverfocus = (*wmh).input == 0;
@@ -2653,20 +3223,8 @@ pub fn updatewindowtype(state: &mut State, c: *mut Client) {
 pub fn setfullscreen(state: &mut State, c: *mut Client, fullscreen: bool) {
     unsafe {
         if fullscreen && !(*c).isfullscreen {
-            xlib::XChangeProperty(
-                state.dpy,
-                (*c).win,
-                state.netatom[Net::WMState as usize],
-                XA_ATOM,
-                32,
-                PropModeReplace,
-                // trying to emulate (unsigned char*)&netatom[NetWMFullscreen],
-                // so take a reference and then cast
-                &state.netatom[Net::WMFullscreen as usize] as *const u64
-                    as *const c_uchar,
-                1,
-            );
             (*c).isfullscreen = true;
+            updatenetwmstate(state, c);
             (*c).oldstate = (*c).isfloating;
             (*c).oldbw = (*c).bw;
             (*c).bw = 0;
@@ -2681,17 +3239,8 @@ pub fn setfullscreen(state: &mut State, c: *mut Client, fullscreen: bool) {
             );
             xlib::XRaiseWindow(state.dpy, (*c).win);
         } else if !fullscreen && (*c).isfullscreen {
-            xlib::XChangeProperty(
-                state.dpy,
-                (*c).win,
-                state.netatom[Net::WMState as usize],
-                XA_ATOM,
-                32,
-                PropModeReplace,
-                std::ptr::null_mut::<c_uchar>(),
-                0,
-            );
             (*c).isfullscreen = false;
+            updatenetwmstate(state, c);
             (*c).isfloating = (*c).oldstate;
             (*c).bw = (*c).oldbw;
             (*c).x = (*c).oldx;
@@ -2700,7 +3249,10 @@ pub fn setfullscreen(state: &mut State, c: *mut Client, fullscreen: bool) {
             (*c).h = (*c).oldh;
             resizeclient(state, c, (*c).x, (*c).y, (*c).w, (*c).h);
             arrange(state, (*c).mon);
+        } else {
+            return;
         }
+        ipc::emit(state, Event::Fullscreen { window: (*c).win, fullscreen });
     }
 }
 
@@ -2762,59 +3314,172 @@ pub fn getsystraywidth(state: &State) -> c_uint {
     }
 }
 
-pub fn applyrules(state: &mut State, c: *mut Client) {
-    log::trace!("applyrules");
+/// The class and instance names from the `WM_CLASS` property of `w`, or
+/// `"broken"` for either one that is missing.
+pub fn getclasshint(state: &mut State, w: Window) -> (String, String) {
     unsafe {
         let mut ch = xlib::XClassHint {
             res_name: std::ptr::null_mut(),
             res_class: std::ptr::null_mut(),
         };
+        xlib::XGetClassHint(state.dpy, w, &mut ch);
+        let get = |s: *mut c_char| {
+            if s.is_null() {
+                BROKEN.to_string_lossy().into_owned()
+            } else {
+                let ret = CStr::from_ptr(s).to_string_lossy().into_owned();
+                xlib::XFree(s.cast());
+                ret
+            }
+        };
+        (get(ch.res_class), get(ch.res_name))
+    }
+}
+
+/// Read the class and instance of `c` again after its `WM_CLASS` changed.
+pub fn updateclass(state: &mut State, c: *mut Client) {
+    unsafe {
+        ((*c).class, (*c).instance) = getclasshint(state, (*c).win);
+    }
+}
+
+/// The `_NET_WM_WINDOW_TYPE` of `c` without its prefix and in lower case, or
+/// an empty string if it doesn't have one.
+fn windowtype(state: &mut State, c: *mut Client) -> String {
+    unsafe {
+        let atom =
+            getatomprop(state, c, state.netatom[Net::WMWindowType as usize]);
+        if atom == 0 {
+            return String::new();
+        }
+        let name = xlib::XGetAtomName(state.dpy, atom);
+        if name.is_null() {
+            return String::new();
+        }
+        let full = CStr::from_ptr(name).to_string_lossy();
+        let ret = full
+            .strip_prefix("_NET_WM_WINDOW_TYPE_")
+            .unwrap_or(&full)
+            .to_lowercase();
+        xlib::XFree(name.cast());
+        ret
+    }
+}
+
+/// The properties of `c` that rules match against.
+fn windowprops(state: &mut State, c: *mut Client) -> WindowProps {
+    unsafe {
+        let mut role = String::new();
+        gettextprop(
+            state.dpy,
+            (*c).win,
+            state.wmatom[WM::WindowRole as usize],
+            &mut role,
+        );
+        WindowProps {
+            class: (*c).class.clone(),
+            instance: (*c).instance.clone(),
+            title: (*c).name.clone(),
+            role,
+            wintype: windowtype(state, c),
+        }
+    }
+}
+
+/// The monitor numbered `num`, or null if there isn't one.
+fn numtomon(state: &State, num: c_int) -> *mut Monitor {
+    unsafe {
+        let mut m = state.mons;
+        while !m.is_null() && (*m).num != num {
+            m = (*m).next;
+        }
+        m
+    }
+}
+
+pub fn applyrules(state: &mut State, c: *mut Client) -> RuleActions {
+    log::trace!("applyrules");
+    unsafe {
         // rule matching
         (*c).isfloating = false;
         (*c).tags = 0;
-        xlib::XGetClassHint(state.dpy, (*c).win, &mut ch);
-        let class = if !ch.res_class.is_null() {
-            CStr::from_ptr(ch.res_class)
+        let props = windowprops(state, c);
+
+        let actions = matchrules(&state.config.rules, &props);
+        (*c).reapplied = actions.reapply.clone();
+        (*c).isterminal = actions.isterminal;
+        (*c).noswallow = actions.noswallow;
+        (*c).isfloating = actions.isfloating;
+        (*c).tags = actions.tags;
+        (*c).nofocus = actions.neverfocus;
+        if let Some(bw) = actions.borderpx {
+            (*c).bw = bw as c_int;
+        }
+        if let Some(num) = actions.monitor {
+            let m = numtomon(state, num);
+            if !m.is_null() {
+                (*c).mon = m;
+            }
+        }
+        let scratchpad = state
+            .config
+            .scratchpads
+            .iter()
+            .find(|s| s.matches(&props.class, &props.title))
+            .map(|s| s.name.clone());
+        (*c).tags = if (*c).tags & state.tagmask() != 0 {
+            (*c).tags & state.tagmask()
         } else {
-            BROKEN
+            (*(*c).mon).tagset[(*(*c).mon).seltags]
         };
-        let instance = if !ch.res_name.is_null() {
-            CStr::from_ptr(ch.res_name)
-        } else {
-            BROKEN
+
+        // scratchpads float on the current tags, whatever the rules say
+        if scratchpad.is_some() {
+            (*c).isfloating = true;
+            (*c).tags = (*(*c).mon).tagset[(*(*c).mon).seltags];
+        }
+        (*c).scratchpad = scratchpad;
+        actions
+    }
+}
+
+/// Apply the rules with `reapply` set again after the class or title of `c`
+/// changed. Nothing happens unless `c` now matches a different set of these
+/// rules than before, so that a window moved by hand stays put while its title
+/// keeps changing.
+pub fn reapplyrules(state: &mut State, c: *mut Client) {
+    log::trace!("reapplyrules");
+    unsafe {
+        if (*c).scratchpad.is_some()
+            || !state.config.rules.iter().any(|r| r.reapply)
+        {
+            return;
+        }
+        let props = windowprops(state, c);
+        let Some(actions) =
+            rematchrules(&state.config.rules, &props, &(*c).reapplied)
+        else {
+            return;
         };
+        (*c).reapplied = actions.reapply;
+        if (*c).reapplied.is_empty() {
+            return;
+        }
 
-        for r in &state.config.rules {
-            if (r.title.is_empty() || (*c).name.contains(&r.title))
-                && (r.class.is_empty()
-                    || class.to_string_lossy().contains(&r.class))
-                && (r.instance.is_empty()
-                    || instance.to_string_lossy().contains(&r.instance))
-            {
-                (*c).isterminal = r.isterminal;
-                (*c).noswallow = r.noswallow;
-                (*c).isfloating = r.isfloating;
-                (*c).tags |= r.tags;
-                let mut m = state.mons;
-                while !m.is_null() && (*m).num != r.monitor {
-                    m = (*m).next;
-                }
-                if !m.is_null() {
-                    (*c).mon = m;
-                }
+        (*c).isfloating = actions.isfloating;
+        let mut mon = (*c).mon;
+        if let Some(num) = actions.monitor {
+            let m = numtomon(state, num);
+            if !m.is_null() {
+                mon = m;
             }
         }
-        if !ch.res_class.is_null() {
-            xlib::XFree(ch.res_class.cast());
-        }
-        if !ch.res_name.is_null() {
-            xlib::XFree(ch.res_name.cast());
+        sendmon(state, c, mon);
+        if actions.tags & state.tagmask() != 0 {
+            (*c).tags = actions.tags & state.tagmask();
         }
-        (*c).tags = if (*c).tags & state.tagmask() != 0 {
-            (*c).tags & state.tagmask()
-        } else {
-            (*(*c).mon).tagset[(*(*c).mon).seltags]
-        };
+        focus(state, null_mut());
+        arrange(state, null_mut());
     }
 }
 
@@ -2838,6 +3503,7 @@ pub fn swallow(state: &mut State, p: *mut Client, c: *mut Client) {
 
         std::mem::swap(&mut p.win, &mut c.win);
         updatetitle(state, p);
+        updateclass(state, p);
         XMoveResizeWindow(state.dpy, p.win, p.x, p.y, p.w as u32, p.h as u32);
         arrange(state, p.mon);
         configure(state, p);
@@ -2851,12 +3517,13 @@ pub fn unswallow(state: &mut State, c: *mut Client) {
 
         c.win = (*c.swallowing).win;
 
-        libc::free(c.swallowing.cast());
+        freeclient(c.swallowing);
         c.swallowing = null_mut();
 
         // unfullscreen the client
         setfullscreen(state, c, false);
         updatetitle(state, c);
+        updateclass(state, c);
         arrange(state, c.mon);
         XMapWindow(state.dpy, c.win);
         XMoveResizeWindow(state.dpy, c.win, c.x, c.y, c.w as u32, c.h as u32);
@@ -2889,6 +3556,160 @@ pub fn updatetitle(state: &mut State, c: *mut Client) {
     }
 }
 
+/// Save the tags, floating state, geometry, monitor, and hidden state of each
+/// client in properties on its window for [restoreclient] to pick up after a
+/// restart.
+pub fn saveclients(state: &mut State) {
+    log::trace!("saveclients");
+    unsafe {
+        let mut m = state.mons;
+        while !m.is_null() {
+            let mut c = (*m).clients;
+            while !c.is_null() {
+                let c_ = &*c;
+                let props: [(Rwm, &[c_long]); 5] = [
+                    (Rwm::Tags, &[c_.tags as c_long]),
+                    (Rwm::Floating, &[c_.isfloating as c_long]),
+                    (
+                        Rwm::Geometry,
+                        &[
+                            c_.x as c_long,
+                            c_.y as c_long,
+                            c_.w as c_long,
+                            c_.h as c_long,
+                        ],
+                    ),
+                    (Rwm::Monitor, &[(*m).num as c_long]),
+                    (Rwm::Hidden, &[c_.ishidden as c_long]),
+                ];
+                for (atom, data) in props {
+                    xlib::XChangeProperty(
+                        state.dpy,
+                        c_.win,
+                        state.rwmatom[atom as usize],
+                        XA_CARDINAL,
+                        32,
+                        PropModeReplace,
+                        data.as_ptr().cast(),
+                        data.len() as c_int,
+                    );
+                }
+                c = c_.next;
+            }
+            m = (*m).next;
+        }
+    }
+}
+
+/// Read the 32-bit values of the CARDINAL property `prop` on `w`, if it is
+/// set.
+fn getcardinalprop(
+    state: &mut State,
+    w: Window,
+    prop: Atom,
+) -> Option<Vec<c_long>> {
+    let mut format = 0;
+    let mut p: *mut c_uchar = std::ptr::null_mut();
+    let mut n = 0;
+    let mut extra = 0;
+    let mut real = 0;
+    unsafe {
+        let cond = xlib::XGetWindowProperty(
+            state.dpy,
+            w,
+            prop,
+            0,
+            4,
+            False,
+            XA_CARDINAL,
+            &mut real,
+            &mut format,
+            &mut n,
+            &mut extra,
+            (&mut p) as *mut *mut c_uchar,
+        );
+        if cond != Success as i32 || p.is_null() {
+            return None;
+        }
+        let ret = (format == 32 && n > 0).then(|| {
+            std::slice::from_raw_parts(p as *const c_long, n as usize).to_vec()
+        });
+        XFree(p.cast());
+        ret
+    }
+}
+
+/// The geometry of the scratchpad `c` placed on its monitor as configured, or
+/// `None` if `c` isn't a scratchpad.
+pub fn scratchgeom(state: &State, c: *mut Client) -> Option<Rect> {
+    unsafe {
+        let name = (*c).scratchpad.as_ref()?;
+        let sp = state.config.scratchpads.iter().find(|s| &s.name == name)?;
+        Some(
+            sp.placement
+                .place(windowarea((*c).mon), (*c).w, (*c).h, (*c).bw),
+        )
+    }
+}
+
+/// The window area of `m`, excluding the bar.
+pub fn windowarea(m: *const Monitor) -> Rect {
+    unsafe { Rect { x: (*m).wx, y: (*m).wy, w: (*m).ww, h: (*m).wh } }
+}
+
+/// Restore the state saved on `c` by [saveclients] before a restart, taking
+/// precedence over the rules applied by [applyrules]. The properties are
+/// deleted afterwards so that they only apply once. Returns whether there was
+/// any state to restore.
+pub fn restoreclient(state: &mut State, c: *mut Client) -> bool {
+    log::trace!("restoreclient");
+    unsafe {
+        let w = (*c).win;
+        let tags = getcardinalprop(state, w, state.rwmatom[Rwm::Tags as usize]);
+        let Some(tags) = tags else {
+            return false;
+        };
+        // hidden scratchpads stay hidden
+        let tags = tags[0] as c_uint & state.tagmask();
+        if tags != 0 || (*c).scratchpad.is_some() {
+            (*c).tags = tags;
+        }
+        let prop = state.rwmatom[Rwm::Monitor as usize];
+        if let Some(num) = getcardinalprop(state, w, prop) {
+            let mut m = state.mons;
+            while !m.is_null() && (*m).num as c_long != num[0] {
+                m = (*m).next;
+            }
+            if !m.is_null() {
+                (*c).mon = m;
+            }
+        }
+        let prop = state.rwmatom[Rwm::Floating as usize];
+        if let Some(floating) = getcardinalprop(state, w, prop) {
+            (*c).isfloating = floating[0] != 0;
+        }
+        let prop = state.rwmatom[Rwm::Geometry as usize];
+        if let Some(&[x, y, width, height]) =
+            getcardinalprop(state, w, prop).as_deref()
+        {
+            (*c).x = x as c_int;
+            (*c).y = y as c_int;
+            (*c).w = width as c_int;
+            (*c).h = height as c_int;
+        }
+        // unmanaging withdrew the window, so manage can't tell that it was
+        // iconic from its WM_STATE
+        let prop = state.rwmatom[Rwm::Hidden as usize];
+        if let Some(hidden) = getcardinalprop(state, w, prop) {
+            (*c).ishidden = hidden[0] != 0;
+        }
+        for atom in state.rwmatom {
+            xlib::XDeleteProperty(state.dpy, w, atom);
+        }
+        true
+    }
+}
+
 pub fn getstate(state: &mut State, w: Window) -> c_long {
     let mut format = 0;
     let mut result: c_long = -1;
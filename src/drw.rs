@@ -12,7 +12,8 @@ use fontconfig_sys::{
 };
 use x11::xft::{self, XftFont};
 use x11::xlib::{
-    self, CapButt, Display, Drawable, False, GC, JoinMiter, LineSolid,
+    self, AllocNone, CapButt, Colormap, Display, Drawable, False, GC,
+    JoinMiter, LineSolid, TrueColor, Visual,
 };
 
 use crate::Clr;
@@ -45,7 +46,42 @@ pub struct Drw {
     pub gc: GC,
     pub scheme: Vec<Clr>,
     pub fonts: Vec<Fnt>,
+    /// Visual, depth, and colormap for the drawable and the windows it's
+    /// copied to
+    pub visual: *mut Visual,
+    pub depth: c_uint,
+    pub cmap: Colormap,
+    /// Whether `visual` has an alpha channel
+    pub argb: bool,
 }
+
+/// Look up a 32-bit TrueColor visual with an alpha channel, returning it along
+/// with a colormap for it.
+pub fn argb_visual(
+    dpy: *mut Display,
+    screen: c_int,
+    root: Window,
+) -> Option<(*mut Visual, Colormap)> {
+    unsafe {
+        let mut vinfo = MaybeUninit::uninit();
+        if xlib::XMatchVisualInfo(
+            dpy,
+            screen,
+            32,
+            TrueColor,
+            vinfo.as_mut_ptr(),
+        ) == 0
+        {
+            return None;
+        }
+        let visual = vinfo.assume_init().visual;
+        Some((visual, xlib::XCreateColormap(dpy, root, visual, AllocNone)))
+    }
+}
+
+/// Create a [Drw] using the default visual, or the ARGB visual from
+/// [argb_visual] if `argb` is true and one is available.
+///
 /// # Safety
 pub unsafe fn create(
     dpy: *mut Display,
@@ -53,24 +89,35 @@ pub unsafe fn create(
     root: Window,
     w: c_uint,
     h: c_uint,
+    argb: bool,
 ) -> Drw {
     unsafe {
+        let (visual, depth, cmap, argb) =
+            match argb.then(|| argb_visual(dpy, screen, root)).flatten() {
+                Some((visual, cmap)) => (visual, 32, cmap, true),
+                None => (
+                    xlib::XDefaultVisual(dpy, screen),
+                    xlib::XDefaultDepth(dpy, screen) as c_uint,
+                    xlib::XDefaultColormap(dpy, screen),
+                    false,
+                ),
+            };
+        let drawable = xlib::XCreatePixmap(dpy, root, w, h, depth);
         let drw = Drw {
             w,
             h,
             dpy,
             screen,
             root,
-            drawable: xlib::XCreatePixmap(
-                dpy,
-                root,
-                w,
-                h,
-                xlib::XDefaultDepth(dpy, screen) as u32,
-            ),
-            gc: xlib::XCreateGC(dpy, root, 0, null_mut()),
+            drawable,
+            // the GC has to match the depth of the drawable, not the root
+            gc: xlib::XCreateGC(dpy, drawable, 0, null_mut()),
             scheme: Vec::new(),
             fonts: Vec::new(),
+            visual,
+            depth,
+            cmap,
+            argb,
         };
         xlib::XSetLineAttributes(dpy, drw.gc, 1, LineSolid, CapButt, JoinMiter);
         drw
@@ -207,6 +254,52 @@ fn xfont_create(
     }
 }
 
+/// Split the alpha channel off of a `#RRGGBBAA` color, returning the rest of
+/// the color and the alpha, which defaults to opaque.
+pub fn split_alpha(name: &str) -> (&str, u8) {
+    if let Some(hex) = name.strip_prefix('#')
+        && hex.len() == 8
+        && let Ok(alpha) = u8::from_str_radix(&hex[6..], 16)
+    {
+        return (&name[..7], alpha);
+    }
+    (name, 0xff)
+}
+
+/// Allocate the color `name`, returning `None` instead of dying if it's invalid.
+/// `name` can include an alpha channel as `#RRGGBBAA`, which is only used when
+/// drawing with an ARGB visual.
+pub fn clr_try_create(drw: &Drw, name: &str) -> Option<Clr> {
+    let (name, alpha) = split_alpha(name);
+    let name = CString::new(name).ok()?;
+    let mut dest = MaybeUninit::uninit();
+    unsafe {
+        let ok = xft::XftColorAllocName(
+            drw.dpy,
+            drw.visual,
+            drw.cmap,
+            name.as_ptr(),
+            dest.as_mut_ptr(),
+        );
+        if ok == 0 {
+            return None;
+        }
+        let mut clr: Clr = dest.assume_init();
+        if drw.argb {
+            // Xft leaves the alpha bits of the pixel unset, which would make
+            // everything fully transparent
+            clr.pixel = (clr.pixel & 0x00ffffff) | ((alpha as u64) << 24);
+            clr.color.alpha = alpha as u16 * 0x101;
+        }
+        Some(clr)
+    }
+}
+
+/// Free a color allocated by [clr_try_create].
+pub fn clr_free(drw: &Drw, clr: &mut Clr) {
+    unsafe { xft::XftColorFree(drw.dpy, drw.visual, drw.cmap, clr) };
+}
+
 impl Drop for Fnt {
     fn drop(&mut self) {
         if !self.pattern.is_null() {
@@ -224,18 +317,10 @@ fn clr_create(drw: &Drw, dest: *mut Clr, clrname: *const c_char) {
         return;
     }
     unsafe {
-        if xft::XftColorAllocName(
-            drw.dpy,
-            xlib::XDefaultVisual(drw.dpy, drw.screen),
-            xlib::XDefaultColormap(drw.dpy, drw.screen),
-            clrname,
-            dest,
-        ) == 0
-        {
-            die(&format!(
-                "error, cannot allocate color '{:?}'",
-                CStr::from_ptr(clrname)
-            ));
+        let name = CStr::from_ptr(clrname).to_string_lossy();
+        match clr_try_create(drw, &name) {
+            Some(clr) => *dest = clr,
+            None => die(&format!("error, cannot allocate color '{name:?}'")),
         }
     }
 }
@@ -348,12 +433,7 @@ pub unsafe fn text(
             log::trace!("text: XFillRectangle");
             xlib::XFillRectangle(drw.dpy, drw.drawable, drw.gc, x, y, w, h);
             log::trace!("text: XftDrawCreate");
-            d = xft::XftDrawCreate(
-                drw.dpy,
-                drw.drawable,
-                xlib::XDefaultVisual(drw.dpy, drw.screen),
-                xlib::XDefaultColormap(drw.dpy, drw.screen),
-            );
+            d = xft::XftDrawCreate(drw.dpy, drw.drawable, drw.visual, drw.cmap);
             x += lpad as i32;
             w -= lpad;
         }
@@ -603,12 +683,6 @@ pub fn resize(drw: &mut Drw, w: c_uint, h: c_uint) {
         if drw.drawable != 0 {
             xlib::XFreePixmap(drw.dpy, drw.drawable);
         }
-        drw.drawable = xlib::XCreatePixmap(
-            drw.dpy,
-            drw.root,
-            w,
-            h,
-            xlib::XDefaultDepth(drw.dpy, drw.screen) as c_uint,
-        );
+        drw.drawable = xlib::XCreatePixmap(drw.dpy, drw.root, w, h, drw.depth);
     }
 }
@@ -10,6 +10,18 @@ pub enum Cur {
 pub enum Scheme {
     Norm,
     Sel,
+    /// Hidden windows on the bar
+    Hid,
+    /// Urgent tags on the bar and the borders of urgent windows
+    Urg,
+    /// Unfocused windows in the title area of the bar
+    TitleNorm,
+    /// The focused window in the title area of the bar
+    TitleSel,
+    /// Unselected tags with windows on them
+    TagOcc,
+    /// The status text
+    Status,
 }
 
 /// Clicks
@@ -30,6 +42,7 @@ pub enum WM {
     Delete,
     State,
     TakeFocus,
+    WindowRole,
     Last,
 }
 
@@ -44,6 +57,7 @@ pub enum Net {
     SystemTrayOrientation,
     SystemTrayOrientationHorz,
     WMFullscreen,
+    WMHidden,
     ActiveWindow,
     WMWindowType,
     WMWindowTypeDialog,
@@ -58,6 +72,16 @@ pub enum XEmbed {
     Last,
 }
 
+/// rwm atoms for carrying client state across a restart
+pub enum Rwm {
+    Tags,
+    Floating,
+    Geometry,
+    Monitor,
+    Hidden,
+    Last,
+}
+
 /// Clr scheme index
 pub enum Col {
     Fg,
@@ -8,28 +8,29 @@ use x11::xlib::{
     self, CWBackPixel, CWBorderWidth, CWHeight, CWWidth, CWX, CWY, CurrentTime,
     False, KeyCode, MappingKeyboard, NotifyInferior, NotifyNormal,
     PropertyChangeMask, PropertyDelete, ReplayPointer, ResizeRedirectMask,
-    StructureNotifyMask, XA_WM_HINTS, XA_WM_NAME, XA_WM_NORMAL_HINTS,
-    XA_WM_TRANSIENT_FOR, XAddToSaveSet, XChangeWindowAttributes, XEvent,
-    XGetWindowAttributes, XMapRaised, XReparentWindow, XSelectInput,
-    XSetWindowAttributes, XSync, XWindowAttributes,
+    StructureNotifyMask, XA_WM_CLASS, XA_WM_HINTS, XA_WM_NAME,
+    XA_WM_NORMAL_HINTS, XA_WM_TRANSIENT_FOR, XAddToSaveSet,
+    XChangeWindowAttributes, XEvent, XGetWindowAttributes, XMapRaised,
+    XReparentWindow, XSelectInput, XSetWindowAttributes, XSync,
+    XWindowAttributes,
 };
 
 use crate::{
-    Arg, Client, Monitor, State, Window, drw,
+    Arg, Monitor, State, Window, drw,
     enums::{Col, Scheme, XEmbed},
-    util::ecalloc,
 };
 
 use crate::{
     NORMAL_STATE, WITHDRAWN_STATE, arrange, cleanmask, configure, drawbar,
     drawbars,
     enums::{Clk, Net},
-    focus, getsystraywidth, grabkeys, height, is_visible, manage, recttomon,
-    removesystrayicon, resizebarwin, resizeclient, restack, sendevent,
-    setclientstate, setfocus, setfullscreen, seturgent, swallowingclient,
-    textw, unfocus, unmanage, updatebars, updategeom, updatesizehints,
-    updatestatus, updatesystray, updatesystrayicongeom, updatesystrayiconstate,
-    updatetitle, updatewindowtype, updatewmhints, width, wintoclient, wintomon,
+    focus, freeclient, getsystraywidth, grabkeys, height, is_visible, manage,
+    newclient, reapplyrules, recttomon, removesystrayicon, resizebarwin,
+    resizeclient, restack, sendevent, setclientstate, setfocus, setfullscreen,
+    seturgent, swallowingclient, textw, unfocus, unmanage, updatebars,
+    updateclass, updategeom, updatesizehints, updatestatus, updatesystray,
+    updatesystrayicongeom, updatesystrayiconstate, updatetitle,
+    updatewindowtype, updatewmhints, width, wintoclient, wintomon,
     wintosystrayicon,
     xembed::{
         SYSTEM_TRAY_REQUEST_DOCK, XEMBED_EMBEDDED_NOTIFY,
@@ -81,9 +82,25 @@ pub fn buttonpress(state: &mut State, e: *mut XEvent) {
                     - textw(&mut state.drw, &state.stext, state.lrpad)
                     - getsystraywidth(state) as i32
             {
+                // clicks on status blocks go to the status program instead
+                if crate::sigstatus(state, ev.x, ev.button) {
+                    return;
+                }
                 click = Clk::StatusText;
             } else {
                 click = Clk::WinTitle;
+                // focus the clicked window, restoring it if it's hidden,
+                // so that the WinTitle buttons act on it
+                let entry = (*state.selmon)
+                    .titles
+                    .iter()
+                    .find(|t| ev.x >= t.x && ev.x < t.x + t.w)
+                    .map(|t| t.client);
+                if let Some(c) = entry {
+                    crate::show(state, c);
+                    crate::focus(state, c);
+                    restack(state, state.selmon);
+                }
             }
         } else {
             let c = wintoclient(state, ev.window);
@@ -103,7 +120,7 @@ pub fn buttonpress(state: &mut State, e: *mut XEvent) {
             {
                 let f = state.config.buttons[i].func.0.unwrap();
                 let a = if click == Clk::TagBar
-                    && state.config.buttons[i].arg.i() == 0
+                    && matches!(state.config.buttons[i].arg, Arg::I(0))
                 {
                     &arg
                 } else {
@@ -126,11 +143,11 @@ pub(crate) fn clientmessage(state: &mut State, e: *mut XEvent) {
         {
             // add systray icons
             if cme.data.get_long(1) == SYSTEM_TRAY_REQUEST_DOCK as c_long {
-                c = ecalloc(1, size_of::<Client>()).cast();
+                c = newclient();
             }
             (*c).win = cme.data.get_long(2) as u64;
             if (*c).win == 0 {
-                libc::free(c.cast());
+                freeclient(c);
                 return;
             }
             (*c).mon = state.selmon;
@@ -145,7 +162,7 @@ pub(crate) fn clientmessage(state: &mut State, e: *mut XEvent) {
                 (*wa.as_mut_ptr()).border_width = 0;
             }
             let wa = wa.assume_init();
-            // Safety: we already returned if c was null in ecalloc. could have
+            // Safety: we already returned if c was null in newclient. could have
             // done this earlier too
             let c = &mut *c;
 
@@ -271,6 +288,19 @@ pub(crate) fn clientmessage(state: &mut State, e: *mut XEvent) {
                             && !(*c).isfullscreen),
                 );
             }
+            if cme.data.get_long(1)
+                == state.netatom[Net::WMHidden as usize] as i64
+                || cme.data.get_long(2)
+                    == state.netatom[Net::WMHidden as usize] as i64
+            {
+                let hide = cme.data.get_long(0) == 1
+                    || (cme.data.get_long(0) == 2 && !(*c).ishidden);
+                if hide {
+                    crate::hide(state, c);
+                } else {
+                    crate::show(state, c);
+                }
+            }
         } else if cme.message_type == state.netatom[Net::ActiveWindow as usize]
             && c != (*state.selmon).sel
             && !(*c).isurgent
@@ -637,6 +667,11 @@ pub(crate) fn propertynotify(state: &mut State, e: *mut XEvent) {
                 if std::ptr::eq(c, (*c.mon).sel) {
                     drawbar(state, c.mon);
                 }
+                reapplyrules(state, c);
+            }
+            if ev.atom == XA_WM_CLASS {
+                updateclass(state, c);
+                reapplyrules(state, c);
             }
             if ev.atom == state.netatom[Net::WMWindowType as usize] {
                 updatewindowtype(state, c);
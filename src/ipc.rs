@@ -0,0 +1,756 @@
+//! Unix socket interface for querying and controlling a running rwm.
+//!
+//! Clients connect to the socket at [`socket_path`] and write one JSON
+//! [`Request`] per line. Each request is answered with a single line of JSON
+//! containing a [`Reply`]. For example, the request
+//!
+//! ```json
+//! {"type": "command", "name": "view", "arg": {"Ui": 4}}
+//! ```
+//!
+//! calls the same `view` handler as a key binding with `{Ui = 4}` in
+//! `config.lua`.
+//!
+//! After a [`Request::Subscribe`], the connection additionally receives a
+//! [`Reply::Event`] line whenever one of the subscribed [`EventKind`]s occurs.
+
+use std::{
+    ffi::{c_int, c_uint},
+    io::{self, ErrorKind, Read, Write},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+};
+
+use libc::{POLLIN, POLLOUT, c_short, nfds_t, pollfd};
+
+use crate::{
+    Arg, Client, Monitor, State, Window,
+    config::key::{ArgKind, FUNC_MAP},
+};
+
+/// The path of the IPC socket for the X display named `display`.
+///
+/// This is `$RWM_SOCKET` if it is set. Otherwise the socket is placed in
+/// `$XDG_RUNTIME_DIR`, or the system temporary directory if that is unset.
+pub fn socket_path(display: &str) -> PathBuf {
+    if let Ok(path) = std::env::var("RWM_SOCKET") {
+        return PathBuf::from(path);
+    }
+    let dir = std::env::var("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| std::env::temp_dir());
+    dir.join(format!("rwm{}.sock", display.replace('/', "_")))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Call one of the handler functions available to key bindings
+    Command {
+        name: String,
+        #[serde(default = "crate::default_button_arg")]
+        arg: Arg,
+    },
+    /// Describe part of the current window manager state
+    Get { what: Query },
+    /// Receive events of the given kinds on this connection, or of every kind
+    /// if `events` is empty
+    Subscribe {
+        #[serde(default)]
+        events: Vec<EventKind>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Query {
+    Monitors,
+    Clients,
+    Tags,
+    Layouts,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reply {
+    Ok,
+    Error {
+        message: String,
+    },
+    Monitors {
+        monitors: Vec<MonitorInfo>,
+    },
+    Clients {
+        clients: Vec<ClientInfo>,
+    },
+    Tags {
+        tags: Vec<String>,
+    },
+    Layouts {
+        layouts: Vec<LayoutInfo>,
+    },
+    Event {
+        #[serde(flatten)]
+        event: Event,
+    },
+}
+
+impl Reply {
+    fn error(message: impl Into<String>) -> Self {
+        Self::Error { message: message.into() }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Geometry {
+    pub x: c_int,
+    pub y: c_int,
+    pub w: c_int,
+    pub h: c_int,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct MonitorInfo {
+    pub num: c_int,
+    pub selected: bool,
+    /// Full monitor geometry
+    pub geometry: Geometry,
+    /// Window area, excluding the bar
+    pub window_area: Geometry,
+    pub tagset: c_uint,
+    pub layout: String,
+    pub mfact: f32,
+    pub nmaster: c_int,
+    pub showbar: bool,
+    pub topbar: bool,
+    /// Window of the focused client, if any
+    pub sel: Option<Window>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ClientInfo {
+    pub window: Window,
+    pub name: String,
+    pub monitor: c_int,
+    pub tags: c_uint,
+    pub geometry: Geometry,
+    pub isfloating: bool,
+    pub isfullscreen: bool,
+    pub isurgent: bool,
+    #[serde(default)]
+    pub ishidden: bool,
+    pub focused: bool,
+    pub pid: libc::pid_t,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct LayoutInfo {
+    pub index: usize,
+    pub symbol: String,
+    /// Name of the arrange function, `lua` for a layout defined in Lua, or
+    /// `None` for the floating layout
+    pub name: Option<String>,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Focus,
+    Tags,
+    Layout,
+    Manage,
+    Unmanage,
+    Fullscreen,
+    Urgent,
+    Monitors,
+}
+
+impl EventKind {
+    const ALL: [Self; 8] = [
+        Self::Focus,
+        Self::Tags,
+        Self::Layout,
+        Self::Manage,
+        Self::Unmanage,
+        Self::Fullscreen,
+        Self::Urgent,
+        Self::Monitors,
+    ];
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The focused client changed, or the focus returned to the root window
+    Focus {
+        client: Option<ClientInfo>,
+    },
+    /// The selected tags on a monitor changed
+    Tags {
+        monitor: c_int,
+        tagset: c_uint,
+    },
+    /// The layout on a monitor changed
+    Layout {
+        monitor: c_int,
+        index: Option<usize>,
+        symbol: String,
+    },
+    /// A new client window was managed
+    Manage {
+        client: ClientInfo,
+    },
+    /// A client window was unmanaged
+    Unmanage {
+        window: Window,
+    },
+    Fullscreen {
+        window: Window,
+        fullscreen: bool,
+    },
+    Urgent {
+        window: Window,
+        urgent: bool,
+    },
+    /// The monitor layout changed
+    Monitors {
+        monitors: Vec<MonitorInfo>,
+    },
+}
+
+impl Event {
+    fn kind(&self) -> EventKind {
+        match self {
+            Event::Focus { .. } => EventKind::Focus,
+            Event::Tags { .. } => EventKind::Tags,
+            Event::Layout { .. } => EventKind::Layout,
+            Event::Manage { .. } => EventKind::Manage,
+            Event::Unmanage { .. } => EventKind::Unmanage,
+            Event::Fullscreen { .. } => EventKind::Fullscreen,
+            Event::Urgent { .. } => EventKind::Urgent,
+            Event::Monitors { .. } => EventKind::Monitors,
+        }
+    }
+}
+
+/// The longest request line accepted from a client, which is dropped instead of
+/// buffering more
+const MAX_REQUEST: usize = 64 * 1024;
+
+/// The most output queued for a client that isn't reading it, such as a stuck
+/// subscriber, before it is dropped
+const MAX_QUEUED: usize = 1024 * 1024;
+
+struct IpcClient {
+    id: u64,
+    stream: UnixStream,
+    buf: Vec<u8>,
+    /// Output not yet written because the socket was full
+    out: Vec<u8>,
+    /// Event kinds this client has subscribed to
+    events: Vec<EventKind>,
+}
+
+impl IpcClient {
+    /// Write as much of the queued output as the socket takes, returning
+    /// whether the client should be kept.
+    fn flush(&mut self) -> bool {
+        while !self.out.is_empty() {
+            match self.stream.write(&self.out) {
+                Ok(n) => {
+                    self.out.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("dropping IPC client after failed write: {e}");
+                    return false;
+                }
+            }
+        }
+        if self.out.len() > MAX_QUEUED {
+            log::warn!("dropping IPC client that stopped reading");
+            return false;
+        }
+        true
+    }
+}
+
+pub struct Ipc {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Vec<IpcClient>,
+    next_id: u64,
+    /// The last window reported in an [Event::Focus], used to avoid repeating
+    /// events for the many calls to [focus](crate::core::focus) that don't
+    /// change anything
+    focused: Option<Window>,
+}
+
+impl Ipc {
+    /// Listen for connections on `path`, replacing any stale socket left
+    /// behind by a previous instance. A socket that another instance is still
+    /// listening on is left alone.
+    pub fn bind(path: PathBuf) -> io::Result<Self> {
+        match UnixStream::connect(&path) {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::AddrInUse,
+                    format!("{} is in use by another instance", path.display()),
+                ));
+            }
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                std::fs::remove_file(&path)?;
+            }
+            // most likely there is nothing there, which bind reports better
+            Err(_) => {}
+        }
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        log::info!("listening for IPC connections on {}", path.display());
+        Ok(Self {
+            path,
+            listener,
+            clients: Vec::new(),
+            next_id: 0,
+            focused: None,
+        })
+    }
+
+    /// The sockets to poll, along with the events to poll them for.
+    fn fds(&self) -> impl Iterator<Item = (RawFd, c_short)> + '_ {
+        std::iter::once((self.listener.as_raw_fd(), POLLIN)).chain(
+            self.clients.iter().map(|c| {
+                let out = if c.out.is_empty() { 0 } else { POLLOUT };
+                (c.stream.as_raw_fd(), POLLIN | out)
+            }),
+        )
+    }
+
+    /// Write as much of the queued output as the sockets take, dropping
+    /// clients that fail.
+    fn flush(&mut self) {
+        self.clients.retain_mut(|client| client.flush());
+    }
+
+    /// Accept any pending connections and collect the complete lines written
+    /// by each client, dropping clients that have disconnected.
+    fn read(&mut self) -> Vec<(u64, String)> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_err() {
+                        continue;
+                    }
+                    self.clients.push(IpcClient {
+                        id: self.next_id,
+                        stream,
+                        buf: Vec::new(),
+                        out: Vec::new(),
+                        events: Vec::new(),
+                    });
+                    self.next_id += 1;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    if e.kind() != ErrorKind::WouldBlock {
+                        log::error!("failed to accept IPC connection: {e}");
+                    }
+                    break;
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        self.clients.retain_mut(|client| {
+            let mut chunk = [0; 4096];
+            loop {
+                match client.stream.read(&mut chunk) {
+                    Ok(0) => return false,
+                    Ok(n) => client.buf.extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return e.kind() == ErrorKind::WouldBlock,
+                }
+                while let Some(i) = client.buf.iter().position(|&b| b == b'\n')
+                {
+                    let line: Vec<u8> = client.buf.drain(..=i).collect();
+                    let line = String::from_utf8_lossy(&line).trim().to_owned();
+                    if !line.is_empty() {
+                        lines.push((client.id, line));
+                    }
+                }
+                if client.buf.len() > MAX_REQUEST {
+                    log::warn!("dropping IPC client after an overlong request");
+                    return false;
+                }
+            }
+        });
+        lines
+    }
+
+    /// Queue `reply` for the client identified by `id` and write as much as
+    /// its socket takes, disconnecting it if the write fails.
+    fn send(&mut self, id: u64, reply: &Reply) {
+        let Some(i) = self.clients.iter().position(|c| c.id == id) else {
+            return;
+        };
+        let client = &mut self.clients[i];
+        serde_json::to_writer(&mut client.out, reply).unwrap();
+        client.out.push(b'\n');
+        if !client.flush() {
+            self.clients.swap_remove(i);
+        }
+    }
+
+    fn subscribe(&mut self, id: u64, events: Vec<EventKind>) {
+        if let Some(client) = self.clients.iter_mut().find(|c| c.id == id) {
+            client.events = if events.is_empty() {
+                EventKind::ALL.to_vec()
+            } else {
+                events
+            };
+        }
+    }
+
+    fn broadcast(&mut self, event: Event) {
+        let kind = event.kind();
+        let reply = Reply::Event { event };
+        let ids: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|c| c.events.contains(&kind))
+            .map(|c| c.id)
+            .collect();
+        for id in ids {
+            self.send(id, &reply);
+        }
+    }
+}
+
+impl Drop for Ipc {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Block until one of `fds`, such as the X connection, or one of the IPC
+/// sockets is readable, then handle any pending IPC requests. Negative `fds`
+/// are ignored.
+pub fn wait(state: &mut State, fds: &[c_int]) {
+    let nfds = fds.len();
+    let mut fds: Vec<_> = fds
+        .iter()
+        .map(|&fd| pollfd { fd, events: POLLIN, revents: 0 })
+        .collect();
+    if let Some(ipc) = &state.ipc {
+        fds.extend(ipc.fds().map(|(fd, events)| pollfd {
+            fd,
+            events,
+            revents: 0,
+        }));
+    }
+    unsafe {
+        libc::poll(fds.as_mut_ptr(), fds.len() as nfds_t, -1);
+    }
+    if fds[nfds..].iter().any(|fd| fd.revents != 0) {
+        dispatch(state);
+    }
+}
+
+fn dispatch(state: &mut State) {
+    let Some(ipc) = &mut state.ipc else {
+        return;
+    };
+    ipc.flush();
+    // the requests are collected up front because handling them requires
+    // mutable access to all of `state`, including `state.ipc`
+    for (id, line) in ipc.read() {
+        log::trace!("ipc request: {line}");
+        let reply = match serde_json::from_str(&line) {
+            Ok(Request::Subscribe { events }) => {
+                if let Some(ipc) = &mut state.ipc {
+                    ipc.subscribe(id, events);
+                }
+                Reply::Ok
+            }
+            Ok(request) => handle(state, request),
+            Err(e) => Reply::error(format!("invalid request: {e}")),
+        };
+        if let Some(ipc) = &mut state.ipc {
+            ipc.send(id, &reply);
+        }
+    }
+}
+
+/// Check that `arg` is something a handler expecting `kind` can take, with
+/// `nlayouts` layouts in the config.
+fn check_arg(kind: ArgKind, arg: &Arg, nlayouts: usize) -> Result<(), String> {
+    if !kind.accepts(arg) {
+        return Err(format!("expected an argument like {kind:?}, not {arg:?}"));
+    }
+    match arg {
+        Arg::L(Some(i)) if *i >= nlayouts => Err(format!(
+            "layout index {i} is out of range for {nlayouts} layouts"
+        )),
+        Arg::V(v) if kind == ArgKind::V && v.is_empty() => {
+            Err("empty command".to_owned())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn handle(state: &mut State, request: Request) -> Reply {
+    match request {
+        Request::Command { name, arg } => {
+            let Some(&(f, kind)) = FUNC_MAP.get(name.as_str()) else {
+                return Reply::error(format!("no handler `{name}`"));
+            };
+            // handlers panic on an unexpected Arg, which is a configuration
+            // error for key bindings but shouldn't take down the window manager
+            // when it comes from a socket
+            if let Err(e) = check_arg(kind, &arg, state.config.layouts.len()) {
+                return Reply::error(format!("`{name}`: {e}"));
+            }
+            f(state, &arg);
+            Reply::Ok
+        }
+        Request::Get { what: Query::Monitors } => {
+            Reply::Monitors { monitors: monitors(state) }
+        }
+        Request::Get { what: Query::Clients } => {
+            Reply::Clients { clients: clients(state) }
+        }
+        Request::Get { what: Query::Tags } => {
+            Reply::Tags { tags: state.config.tags.clone() }
+        }
+        Request::Get { what: Query::Layouts } => Reply::Layouts {
+            layouts: state
+                .config
+                .layouts
+                .iter()
+                .enumerate()
+                .map(|(index, l)| LayoutInfo {
+                    index,
+                    symbol: l.symbol.clone(),
+                    name: l.arrange.as_ref().map(|a| a.name().to_owned()),
+                })
+                .collect(),
+        },
+        Request::Subscribe { .. } => unreachable!("handled in dispatch"),
+    }
+}
+
+/// Send `event` to every IPC client subscribed to its kind.
+pub fn emit(state: &mut State, event: Event) {
+    if let Some(ipc) = &mut state.ipc {
+        ipc.broadcast(event);
+    }
+}
+
+/// Emit an [Event::Focus] for `c`, unless it was already the last focused
+/// window reported.
+pub fn emit_focus(state: &mut State, c: *mut Client) {
+    let Some(ipc) = &mut state.ipc else {
+        return;
+    };
+    let window = unsafe { (!c.is_null()).then(|| (*c).win) };
+    if ipc.focused == window {
+        return;
+    }
+    ipc.focused = window;
+    let client = (!c.is_null()).then(|| client_info(c));
+    ipc.broadcast(Event::Focus { client });
+}
+
+/// Emit an [Event::Tags] for the selected tags on `m`.
+pub fn emit_tags(state: &mut State, m: *mut Monitor) {
+    let (monitor, tagset) = unsafe { ((*m).num, (*m).tagset[(*m).seltags]) };
+    emit(state, Event::Tags { monitor, tagset });
+}
+
+/// Emit an [Event::Layout] for the current layout on `m`.
+pub fn emit_layout(state: &mut State, m: *mut Monitor) {
+    let (monitor, symbol, lt) =
+        unsafe { ((*m).num, (*m).ltsymbol.clone(), (*m).lt[(*m).sellt]) };
+    let index = state
+        .config
+        .layouts
+        .iter()
+        .position(|l| std::ptr::eq(l, lt));
+    emit(state, Event::Layout { monitor, index, symbol });
+}
+
+/// Emit an [Event::Monitors] describing every monitor.
+pub fn emit_monitors(state: &mut State) {
+    if state.ipc.is_some() {
+        let monitors = monitors(state);
+        emit(state, Event::Monitors { monitors });
+    }
+}
+
+fn monitor_info(state: &State, m: *mut Monitor) -> MonitorInfo {
+    unsafe {
+        let m = &*m;
+        MonitorInfo {
+            num: m.num,
+            selected: std::ptr::eq(m, state.selmon),
+            geometry: Geometry { x: m.mx, y: m.my, w: m.mw, h: m.mh },
+            window_area: Geometry { x: m.wx, y: m.wy, w: m.ww, h: m.wh },
+            tagset: m.tagset[m.seltags],
+            layout: m.ltsymbol.clone(),
+            mfact: m.mfact,
+            nmaster: m.nmaster,
+            showbar: m.showbar,
+            topbar: m.topbar,
+            sel: (!m.sel.is_null()).then(|| (*m.sel).win),
+        }
+    }
+}
+
+pub(crate) fn client_info(c: *mut Client) -> ClientInfo {
+    unsafe {
+        let c = &*c;
+        ClientInfo {
+            window: c.win,
+            name: c.name.clone(),
+            monitor: (*c.mon).num,
+            tags: c.tags,
+            geometry: Geometry { x: c.x, y: c.y, w: c.w, h: c.h },
+            isfloating: c.isfloating,
+            isfullscreen: c.isfullscreen,
+            isurgent: c.isurgent,
+            ishidden: c.ishidden,
+            focused: std::ptr::eq(c, (*c.mon).sel),
+            pid: c.pid,
+        }
+    }
+}
+
+fn monitors(state: &State) -> Vec<MonitorInfo> {
+    let mut ret = Vec::new();
+    unsafe {
+        let mut m = state.mons;
+        while !m.is_null() {
+            ret.push(monitor_info(state, m));
+            m = (*m).next;
+        }
+    }
+    ret
+}
+
+fn clients(state: &State) -> Vec<ClientInfo> {
+    let mut ret = Vec::new();
+    unsafe {
+        let mut m = state.mons;
+        while !m.is_null() {
+            let mut c = (*m).clients;
+            while !c.is_null() {
+                ret.push(client_info(c));
+                c = (*c).next;
+            }
+            m = (*m).next;
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use super::*;
+
+    /// A socket path unique to this process and `name`.
+    fn socket(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("rwm-{name}-{}.sock", std::process::id()))
+    }
+
+    #[test]
+    fn bind() {
+        let path = socket("bind");
+
+        // a socket nobody listens on any more is replaced
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let ipc = Ipc::bind(path.clone()).unwrap();
+
+        // but a live one is not
+        let err = Ipc::bind(path.clone()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::AddrInUse);
+        assert!(UnixStream::connect(&path).is_ok());
+
+        drop(ipc);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn overlong_request() {
+        let mut ipc = Ipc::bind(socket("read")).unwrap();
+        let mut ok = UnixStream::connect(&ipc.path).unwrap();
+        let mut bad = UnixStream::connect(&ipc.path).unwrap();
+        ok.write_all(b"{\"type\": \"get\"}\n").unwrap();
+        assert_eq!(ipc.read(), [(0, "{\"type\": \"get\"}".to_owned())]);
+        assert_eq!(ipc.clients.len(), 2);
+
+        // a client that never finishes its line is dropped
+        bad.set_nonblocking(true).unwrap();
+        let junk = [b'x'; 4096];
+        while ipc.clients.len() == 2 {
+            while bad.write(&junk).is_ok() {}
+            ipc.read();
+        }
+        assert_eq!(ipc.clients[0].id, 0);
+    }
+
+    #[test]
+    fn slow_reader() {
+        let mut ipc = Ipc::bind(socket("send")).unwrap();
+        let peer = UnixStream::connect(&ipc.path).unwrap();
+        ipc.read();
+
+        // more than fits in the socket buffer, but less than MAX_QUEUED
+        let n = 2000;
+        for _ in 0..n {
+            ipc.send(0, &Reply::error("x".repeat(100)));
+        }
+        assert_eq!(ipc.clients.len(), 1);
+        assert!(!ipc.clients[0].out.is_empty());
+
+        // everything arrives intact once the peer reads
+        let reader = std::thread::spawn(move || {
+            BufReader::new(peer)
+                .lines()
+                .take(n)
+                .map(Result::unwrap)
+                .collect()
+        });
+        while !ipc.clients[0].out.is_empty() {
+            ipc.flush();
+        }
+        let lines: Vec<String> = reader.join().unwrap();
+        assert_eq!(lines.len(), n);
+        assert!(lines.iter().all(|l| l.len() > 100 && l.ends_with('}')));
+    }
+
+    #[test]
+    fn checked_args() {
+        let check = |func, arg| {
+            let (_, kind) = FUNC_MAP[func];
+            check_arg(kind, &arg, 3)
+        };
+        assert!(check("view", Arg::Ui(1)).is_ok());
+        assert!(check("zoom", Arg::I(7)).is_ok());
+        assert!(check("setlayout", Arg::L(None)).is_ok());
+        assert!(check("setlayout", Arg::L(Some(2))).is_ok());
+        assert!(check("view", Arg::I(1)).is_err());
+        assert!(check("togglescratch", Arg::I(1)).is_err());
+        assert!(check("setlayout", Arg::L(Some(3))).is_err());
+        assert!(check("spawn", Arg::V(vec![])).is_err());
+    }
+}
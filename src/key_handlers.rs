@@ -3,6 +3,7 @@ use std::ffi::c_int;
 use std::os::unix::process::CommandExt;
 use std::process::Command;
 use std::ptr::null_mut;
+use std::sync::atomic::Ordering;
 
 use x11::xlib::{
     ButtonRelease, CWY, ConfigureRequest, CurrentTime, DestroyAll,
@@ -14,13 +15,15 @@ use x11::xlib::{
 };
 
 use crate::core::{
-    HANDLER, MOUSEMASK, XNONE, arrange, attach, attachstack, detach,
-    detachstack, drawbar, focus, getrootptr, height, is_visible, nexttiled,
-    pop, recttomon, resize, resizebarwin, restack, sendevent, setfullscreen,
-    unfocus, updatebarpos, width, xerror, xerrordummy,
+    HANDLER, MOUSEMASK, RELOAD, XNONE, arrange, attach, attachstack, can_focus,
+    detach, detachstack, drawbar, focus, getrootptr, height, hide, is_visible,
+    nexttiled, on_seltags, pop, recttomon, resize, resizebarwin, restack,
+    scratchgeom, sendevent, setclientstate, setfullscreen, show, unfocus,
+    updatebarpos, width, xerror, xerrordummy,
 };
 use crate::enums::WM;
-use crate::{Arg, Client, Monitor};
+use crate::ipc;
+use crate::{Arg, Client, Gaps, Monitor, NORMAL_STATE};
 use crate::{State, cfor};
 
 pub(crate) fn togglebar(state: &mut State, _arg: *const Arg) {
@@ -71,19 +74,19 @@ pub(crate) fn focusstack(state: &mut State, arg: *const Arg) {
             return;
         }
         if (*arg).i() > 0 {
-            cfor!((c = (*(*state.selmon).sel).next; !c.is_null() && !is_visible(c); c = (*c).next) {});
+            cfor!((c = (*(*state.selmon).sel).next; !c.is_null() && !can_focus(c); c = (*c).next) {});
             if c.is_null() {
-                cfor!((c = (*state.selmon).clients; !c.is_null() && !is_visible(c); c = (*c).next) {});
+                cfor!((c = (*state.selmon).clients; !c.is_null() && !can_focus(c); c = (*c).next) {});
             }
         } else {
             cfor!((i = (*state.selmon).clients; i != (*state.selmon).sel; i = (*i).next) {
-                if is_visible(i) {
+                if can_focus(i) {
                     c = i;
                 }
             });
             if c.is_null() {
                 cfor!((; !i.is_null(); i = (*i).next) {
-                    if is_visible(i) {
+                    if can_focus(i) {
                         c = i;
                     }
                 });
@@ -107,6 +110,91 @@ pub(crate) fn incnmaster(state: &mut State, arg: *const Arg) {
     }
 }
 
+/// Grow both the inner and outer gaps by `arg.i` pixels, or shrink them if it
+/// is negative.
+pub(crate) fn incgaps(state: &mut State, arg: *const Arg) {
+    unsafe {
+        let i = (*arg).i();
+        setgaps(state, |gaps| {
+            gaps.inner += i;
+            gaps.outer += i;
+        });
+    }
+}
+
+/// Grow the gaps between windows by `arg.i` pixels.
+pub(crate) fn incigaps(state: &mut State, arg: *const Arg) {
+    unsafe {
+        let i = (*arg).i();
+        setgaps(state, |gaps| gaps.inner += i);
+    }
+}
+
+/// Grow the gaps between windows and the edge of the monitor by `arg.i` pixels.
+pub(crate) fn incogaps(state: &mut State, arg: *const Arg) {
+    unsafe {
+        let i = (*arg).i();
+        setgaps(state, |gaps| gaps.outer += i);
+    }
+}
+
+/// Hide or show the gaps on the current tag, keeping their sizes.
+pub(crate) fn togglegaps(state: &mut State, _arg: *const Arg) {
+    setgaps(state, |gaps| gaps.enabled = !gaps.enabled);
+}
+
+/// Hide the selected window, leaving it listed on the bar.
+pub(crate) fn hidewin(state: &mut State, _arg: *const Arg) {
+    unsafe {
+        hide(state, (*state.selmon).sel);
+    }
+}
+
+/// Show and focus the most recently focused hidden window on the current tags.
+pub(crate) fn showwin(state: &mut State, _arg: *const Arg) {
+    unsafe {
+        let mut c;
+        cfor!((c = (*state.selmon).stack; !c.is_null(); c = (*c).snext) {
+            if (*c).ishidden && on_seltags(c) {
+                break;
+            }
+        });
+        if !c.is_null() {
+            show(state, c);
+            focus(state, c);
+            restack(state, state.selmon);
+        }
+    }
+}
+
+/// Show every hidden window on the current tags.
+pub(crate) fn showall(state: &mut State, _arg: *const Arg) {
+    unsafe {
+        let mut c;
+        cfor!((c = (*state.selmon).clients; !c.is_null(); c = (*c).next) {
+            if (*c).ishidden && on_seltags(c) {
+                setclientstate(state, c, NORMAL_STATE);
+            }
+        });
+        focus(state, null_mut());
+        arrange(state, state.selmon);
+    }
+}
+
+/// Apply `f` to the gaps of the current tag on the selected monitor.
+fn setgaps(state: &mut State, f: impl FnOnce(&mut Gaps)) {
+    unsafe {
+        let monitor = &mut *state.selmon;
+        let mut gaps = monitor.gaps;
+        f(&mut gaps);
+        gaps.inner = gaps.inner.max(0);
+        gaps.outer = gaps.outer.max(0);
+        monitor.pertag.gaps[monitor.pertag.curtag] = gaps;
+        monitor.gaps = gaps;
+        arrange(state, state.selmon);
+    }
+}
+
 /// Set the fraction of the screen occupied by the master window. An `arg.f`
 /// greater than 1.0 sets the fraction absolutely, while fractional values add
 /// to the current value. Total values are restricted to the range [0.05, 0.95]
@@ -135,6 +223,28 @@ pub(crate) fn setmfact(state: &mut State, arg: *const Arg) {
     }
 }
 
+/// Change the weight of the selected window in the layout by `arg.f`, or reset
+/// it if `arg.f` is 0. The weight is restricted to the range [0.25, 4.0].
+pub(crate) fn setcfact(state: &mut State, arg: *const Arg) {
+    unsafe {
+        let c = (*state.selmon).sel;
+        if arg.is_null()
+            || c.is_null()
+            || (*(*state.selmon).lt[(*state.selmon).sellt])
+                .arrange
+                .is_none()
+        {
+            return;
+        }
+        let f = if (*arg).f() == 0.0 { 1.0 } else { (*arg).f() + (*c).cfact };
+        if !(0.25..=4.0).contains(&f) {
+            return;
+        }
+        (*c).cfact = f;
+        arrange(state, state.selmon);
+    }
+}
+
 /// Move the selected window to the master area. The current master is pushed to
 /// the top of the stack.
 pub(crate) fn zoom(state: &mut State, _arg: *const Arg) {
@@ -190,6 +300,7 @@ pub(crate) fn view(state: &mut State, arg: *const Arg) {
         (*state.selmon).nmaster = pertag.nmasters[pertag.curtag];
         (*state.selmon).mfact = pertag.mfacts[pertag.curtag];
         (*state.selmon).sellt = pertag.sellts[pertag.curtag];
+        (*state.selmon).gaps = pertag.gaps[pertag.curtag];
         (*state.selmon).lt[(*state.selmon).sellt] =
             pertag.ltidxs[pertag.curtag][(*state.selmon).sellt];
         (*state.selmon).lt[(*state.selmon).sellt ^ 1] =
@@ -201,6 +312,8 @@ pub(crate) fn view(state: &mut State, arg: *const Arg) {
 
         focus(state, null_mut());
         arrange(state, state.selmon);
+        ipc::emit_tags(state, state.selmon);
+        crate::lua::tag_view_hook(state, state.selmon);
     }
 }
 
@@ -258,6 +371,7 @@ pub(crate) fn setlayout(state: &mut State, arg: *const Arg) {
         } else {
             drawbar(state, state.selmon);
         }
+        ipc::emit_layout(state, state.selmon);
     }
 }
 
@@ -381,7 +495,7 @@ pub(crate) fn focusmon(state: &mut State, arg: *const Arg) {
     }
 }
 
-fn sendmon(state: &mut State, c: *mut Client, m: *mut Monitor) {
+pub(crate) fn sendmon(state: &mut State, c: *mut Client, m: *mut Monitor) {
     unsafe {
         if (*c).mon == m {
             return;
@@ -433,6 +547,7 @@ pub(crate) fn toggleview(state: &mut State, arg: *const Arg) {
 
             // apply settings for this view
             monitor.nmaster = monitor.pertag.nmasters[monitor.pertag.curtag];
+            monitor.gaps = monitor.pertag.gaps[monitor.pertag.curtag];
             monitor.mfact = monitor.pertag.mfacts[monitor.pertag.curtag];
             monitor.sellt = monitor.pertag.sellts[monitor.pertag.curtag];
             monitor.lt[monitor.sellt] =
@@ -447,6 +562,8 @@ pub(crate) fn toggleview(state: &mut State, arg: *const Arg) {
 
             focus(state, null_mut());
             arrange(state, state.selmon);
+            ipc::emit_tags(state, state.selmon);
+            crate::lua::tag_view_hook(state, state.selmon);
         }
     }
 }
@@ -455,6 +572,24 @@ pub(crate) fn quit(state: &mut State, _arg: *const Arg) {
     state.running = false;
 }
 
+/// Quit and exec a new rwm, which picks up the tags, monitor, and floating
+/// state of each client from the properties saved by
+/// [saveclients](crate::core::saveclients).
+pub(crate) fn restart(state: &mut State, _arg: *const Arg) {
+    state.restart = true;
+    state.running = false;
+}
+
+/// Call the Lua function registered as callback `arg.lua`.
+pub(crate) fn lua(state: &mut State, arg: *const Arg) {
+    unsafe { crate::lua::callback(state, (*arg).lua()) }
+}
+
+/// Reload `config.lua` once the current event has been handled.
+pub(crate) fn reload(_state: &mut State, _arg: *const Arg) {
+    RELOAD.store(true, Ordering::Relaxed);
+}
+
 // these are shared between movemouse and resizemouse
 const CONFIGURE_REQUEST: i32 = ConfigureRequest;
 const EXPOSE: i32 = Expose;
@@ -694,8 +829,6 @@ pub(crate) fn spawn(state: &mut State, arg: *const Arg) {
             argv.push((*state.selmon).num.to_string());
         }
 
-        (*state.selmon).tagset[(*state.selmon).seltags] &= !state.scratchtag();
-
         let mut cmd = Command::new(argv[0].clone());
         let cmd = if argv.len() > 1 { cmd.args(&argv[1..]) } else { &mut cmd };
 
@@ -708,9 +841,9 @@ pub(crate) fn spawn(state: &mut State, arg: *const Arg) {
             Ok(())
         });
 
-        let Ok(_) = cmd.spawn() else {
-            panic!("rwm: spawn '{:?}' failed", argv[0]);
-        };
+        if let Err(e) = cmd.spawn() {
+            log::error!("spawn {:?} failed: {e}", argv[0]);
+        }
     }
 }
 
@@ -747,33 +880,80 @@ pub(crate) fn fullscreen(state: &mut State, _: *const Arg) {
     }
 }
 
+/// Show or hide the scratchpad named `arg.name`, spawning it if it isn't
+/// running.
 pub(crate) fn togglescratch(state: &mut State, arg: *const Arg) {
     unsafe {
-        let mut c: *mut Client;
-        let mut found = false;
-        cfor!((
-        c = (*state.selmon).clients;
-        !c.is_null();
-        c = (*c).next) {
-            found = ((*c).tags & state.scratchtag()) != 0;
-            if found {
-                break;
+        let name = match &*arg {
+            Arg::Name(name) => name.clone(),
+            // configs from before scratchpads had names pass the command
+            // instead, which picks the scratchpad with that command or else
+            // the first one
+            Arg::V(cmd) => {
+                let scratchpads = &state.config.scratchpads;
+                match scratchpads
+                    .iter()
+                    .find(|s| &s.cmd == cmd)
+                    .or(scratchpads.first())
+                {
+                    Some(sp) => sp.name.clone(),
+                    None => {
+                        log::warn!("togglescratch: no scratchpads configured");
+                        return;
+                    }
+                }
             }
-        });
-        if found {
-            let newtagset = (*state.selmon).tagset[(*state.selmon).seltags]
-                ^ state.scratchtag();
-            if newtagset != 0 {
-                (*state.selmon).tagset[(*state.selmon).seltags] = newtagset;
-                focus(state, null_mut());
-                arrange(state, state.selmon);
+            arg => {
+                log::warn!(
+                    "togglescratch: expected a scratchpad {{name = ...}}, \
+                     got {arg:?}"
+                );
+                return;
             }
-            if is_visible(c) {
-                focus(state, c);
-                restack(state, state.selmon);
+        };
+        let Some(sp) = state.config.scratchpads.iter().find(|s| s.name == name)
+        else {
+            log::warn!("togglescratch: no scratchpad named `{name}`");
+            return;
+        };
+        let cmd = sp.cmd.clone();
+        let mut c: *mut Client = null_mut();
+        let mut m = state.mons;
+        while c.is_null() && !m.is_null() {
+            c = (*m).clients;
+            while !c.is_null() && (*c).scratchpad.as_ref() != Some(&name) {
+                c = (*c).next;
             }
+            m = (*m).next;
+        }
+        if c.is_null() {
+            spawn(state, &Arg::V(cmd));
+        } else if is_visible(c) && (*c).mon == state.selmon {
+            (*c).tags = 0;
+            focus(state, null_mut());
+            arrange(state, (*c).mon);
         } else {
-            spawn(state, arg);
+            // follow the selected monitor, taking it from the other one even
+            // if it is showing there
+            if (*c).mon != state.selmon {
+                let prev = (*c).mon;
+                unfocus(state, c, false);
+                detach(c);
+                detachstack(c);
+                (*c).mon = state.selmon;
+                attach(c);
+                attachstack(c);
+                if let Some(r) = scratchgeom(state, c) {
+                    resize(state, c, r.x, r.y, r.w, r.h, 0);
+                }
+                arrange(state, prev);
+            }
+            (*c).tags = (*(*c).mon).tagset[(*(*c).mon).seltags];
+            // also bring it back if it was hidden with hidewin
+            show(state, c);
+            focus(state, c);
+            arrange(state, (*c).mon);
+            restack(state, (*c).mon);
         }
     }
 }
@@ -1,11 +1,83 @@
-use std::{cmp::min, ffi::c_uint};
-
 use libc::c_int;
 
-use crate::{Monitor, State};
-use crate::{cfor, height, is_visible, nexttiled, resize};
+use crate::{Client, Monitor, State};
+use crate::{cfor, is_visible, nexttiled, resize};
+
+use geometry::{Constraints, Layout, Params, Rect};
+
+pub mod geometry;
+
+/// The inner and outer gaps to leave on `m` when it has `n` tiled clients.
+pub(crate) fn gaps(state: &State, m: *mut Monitor, n: c_int) -> (c_int, c_int) {
+    let gaps = unsafe { (*m).gaps };
+    if !gaps.enabled || (state.config.smartgaps && n == 1) {
+        (0, 0)
+    } else {
+        (gaps.inner, gaps.outer)
+    }
+}
+
+/// Arrange the tiled clients on `m` with `layout`, returning the number of
+/// tiled clients. `smart_n` overrides the number of clients that smartgaps
+/// goes by.
+fn arrange_with(
+    state: &mut State,
+    m: *mut Monitor,
+    layout: Layout,
+    smart_n: Option<usize>,
+) -> usize {
+    let mut clients: Vec<*mut Client> = Vec::new();
+    unsafe {
+        let mut c = nexttiled((*m).clients);
+        while !c.is_null() {
+            clients.push(c);
+            c = nexttiled((*c).next);
+        }
+    }
+    let n = clients.len();
+    if n == 0 {
+        return 0;
+    }
+    let gaps = unsafe { (*m).gaps };
+    let (gappi, gappo) =
+        if gaps.enabled { (gaps.inner, gaps.outer) } else { (0, 0) };
+    let params = unsafe {
+        Params {
+            area: Rect { x: (*m).wx, y: (*m).wy, w: (*m).ww, h: (*m).wh },
+            mfact: (*m).mfact,
+            nmaster: (*m).nmaster.max(0) as usize,
+            gappi,
+            gappo,
+            smartgaps: state.config.smartgaps,
+        }
+    };
+    // minimum sizes only apply to tiled clients with resize_hints
+    let hints = state.config.resize_hints;
+    let constraints: Vec<_> = clients
+        .iter()
+        .map(|&c| unsafe {
+            Constraints {
+                bw: (*c).bw,
+                minw: if hints { (*c).minw } else { 0 },
+                minh: if hints { (*c).minh } else { 0 },
+                cfact: (*c).cfact,
+            }
+        })
+        .collect();
+    let rects = geometry::arrange_smart(
+        layout,
+        &params,
+        &constraints,
+        smart_n.unwrap_or(n),
+    );
+    for (c, Rect { x, y, w, h }) in clients.into_iter().zip(rects) {
+        resize(state, c, x, y, w, h, 0);
+    }
+    n
+}
 
 pub(crate) fn monocle(state: &mut State, m: *mut Monitor) {
+    log::trace!("monocle");
     unsafe {
         let mut n = 0;
         let mut c;
@@ -18,77 +90,59 @@ pub(crate) fn monocle(state: &mut State, m: *mut Monitor) {
             // override layout symbol
             (*m).ltsymbol = format!("[{n}]");
         }
-        cfor!((c = nexttiled((*m).clients); !c.is_null(); c = nexttiled((*c).next)) {
-            resize(state, c, (*m).wx, (*m).wy, (*m).ww - 2 * (*c).bw, (*m).wh - 2 * (*c).bw, 0);
-        });
     }
+    // only one client is ever visible, so smartgaps always applies
+    arrange_with(state, m, geometry::monocle, Some(1));
 }
 
 pub(crate) fn tile(state: &mut State, m: *mut Monitor) {
     log::trace!("tile");
-    unsafe {
-        let mut i;
-        let mut n;
-        let mut h;
-        let mut c;
-        let mut my;
-        let mut ty;
+    arrange_with(state, m, geometry::tile, None);
+}
 
-        cfor!((
-            (n, c) = (0, nexttiled((*m).clients));
-            !c.is_null();
-            (n, c) = (n + 1, nexttiled((*c).next))
-        ) {});
+pub(crate) fn bstack(state: &mut State, m: *mut Monitor) {
+    log::trace!("bstack");
+    arrange_with(state, m, geometry::bstack, None);
+}
 
-        if n == 0 {
-            return;
-        }
+pub(crate) fn bstackhoriz(state: &mut State, m: *mut Monitor) {
+    log::trace!("bstackhoriz");
+    arrange_with(state, m, geometry::bstackhoriz, None);
+}
 
-        let mw = if n > (*m).nmaster {
-            if (*m).nmaster != 0 {
-                ((*m).ww as f32 * (*m).mfact) as c_uint
-            } else {
-                0
-            }
-        } else {
-            (*m).ww as c_uint
-        };
+pub(crate) fn centeredmaster(state: &mut State, m: *mut Monitor) {
+    log::trace!("centeredmaster");
+    arrange_with(state, m, geometry::centeredmaster, None);
+}
 
-        cfor!((
-            (i, my, ty, c) = (0, 0, 0, nexttiled((*m).clients));
-            !c.is_null();
-            (c, i) = (nexttiled((*c).next), i+1)
-        ) {
-            if i < (*m).nmaster {
-                h = ((*m).wh - my) / (min(n, (*m).nmaster) - i);
-                resize(
-                    state,
-                    c,
-                    (*m).wx,
-                    (*m).wy + my,
-                    mw as c_int - (2*(*c).bw),
-                    h - (2*(*c).bw),
-                    0
-                );
-                if my + height(c) < (*m).wh {
-                    my += height(c);
-                }
+pub(crate) fn centeredfloatingmaster(state: &mut State, m: *mut Monitor) {
+    log::trace!("centeredfloatingmaster");
+    arrange_with(state, m, geometry::centeredfloatingmaster, None);
+}
 
-            } else {
-                h = ((*m).wh - ty) / (n - i);
-                resize(
-                    state,
-                    c,
-                    (*m).wx + mw as c_int,
-                    (*m).wy + ty,
-                    (*m).ww - mw as c_int - (2*(*c).bw),
-                    h - (2*(*c).bw),
-                    0
-                );
-                if ty + height(c) < (*m).wh {
-                    ty += height(c);
-                }
-            }
-        });
+pub(crate) fn grid(state: &mut State, m: *mut Monitor) {
+    log::trace!("grid");
+    arrange_with(state, m, geometry::grid, None);
+}
+
+pub(crate) fn spiral(state: &mut State, m: *mut Monitor) {
+    log::trace!("spiral");
+    arrange_with(state, m, geometry::spiral, None);
+}
+
+pub(crate) fn dwindle(state: &mut State, m: *mut Monitor) {
+    log::trace!("dwindle");
+    arrange_with(state, m, geometry::dwindle, None);
+}
+
+pub(crate) fn deck(state: &mut State, m: *mut Monitor) {
+    log::trace!("deck");
+    let n = arrange_with(state, m, geometry::deck, None);
+    unsafe {
+        let nmaster = (*m).nmaster.max(0) as usize;
+        if n > nmaster {
+            // override layout symbol with the number of stacked clients
+            (*m).ltsymbol = format!("D {}", n - nmaster);
+        }
     }
 }
@@ -0,0 +1,640 @@
+//! Window geometry for the layouts, computed without touching X so that it can
+//! be tested directly.
+//!
+//! [arrange] takes the [Params] of a monitor, a [Layout], and the
+//! [Constraints] of each tiled client in stack order, and returns the
+//! geometry of each client, excluding its border, ready to pass to `resize`.
+//!
+//! The layouts themselves fill the area with cells that include the borders
+//! and one inner gap, so that they don't have to deal with either. [arrange]
+//! takes care of converting between the two.
+
+use std::cmp::{max, min};
+
+use libc::c_int;
+
+/// A rectangle in root window coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: c_int,
+    pub y: c_int,
+    pub w: c_int,
+    pub h: c_int,
+}
+
+/// Where to put a floating window in the area of its monitor. Each value is a
+/// fraction of the area if it is at most 1, or a number of pixels otherwise,
+/// so a position of 1 is the right or bottom edge rather than 1 pixel in.
+/// Without a size the window keeps its own, and without a position it is
+/// centered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(default)]
+pub struct Placement {
+    /// Offset from the left of the area
+    pub x: Option<f32>,
+    /// Offset from the top of the area
+    pub y: Option<f32>,
+    /// Width, including the border
+    pub w: Option<f32>,
+    /// Height, including the border
+    pub h: Option<f32>,
+}
+
+impl Placement {
+    /// Place a `w`x`h` window with a border of `bw` in `area`, returning its
+    /// geometry excluding the border.
+    pub fn place(&self, area: Rect, w: c_int, h: c_int, bw: c_int) -> Rect {
+        let dim = |v: f32, total: c_int| {
+            if v <= 1.0 { (v * total as f32) as c_int } else { v as c_int }
+        };
+        let w = self.w.map_or(w, |v| max(dim(v, area.w) - 2 * bw, 1));
+        let h = self.h.map_or(h, |v| max(dim(v, area.h) - 2 * bw, 1));
+        let x = self.x.map_or((area.w - w - 2 * bw) / 2, |v| dim(v, area.w));
+        let y = self.y.map_or((area.h - h - 2 * bw) / 2, |v| dim(v, area.h));
+        Rect { x: area.x + x, y: area.y + y, w, h }
+    }
+}
+
+/// The monitor settings a layout depends on.
+#[derive(Clone, Copy, Debug)]
+pub struct Params {
+    /// The area to fill with windows
+    pub area: Rect,
+    /// Fraction of the area given to the master area
+    pub mfact: f32,
+    /// Number of clients in the master area
+    pub nmaster: usize,
+    /// Gap between windows
+    pub gappi: c_int,
+    /// Gap between windows and the edge of `area`
+    pub gappo: c_int,
+    /// Whether to drop both gaps when there is only one client
+    pub smartgaps: bool,
+}
+
+/// The size constraints of a client that a layout has to respect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Constraints {
+    /// Border width
+    pub bw: c_int,
+    /// Minimum width, excluding the border, or 0 for none
+    pub minw: c_int,
+    /// Minimum height, excluding the border, or 0 for none
+    pub minh: c_int,
+    /// Weight of the client relative to the others sharing a row or column
+    /// with it
+    pub cfact: f32,
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Self { bw: 0, minw: 0, minh: 0, cfact: 1.0 }
+    }
+}
+
+/// A function computing the cell of each client from the [Params] and the
+/// [Constraints] of the cells.
+pub type Layout = fn(&Params, &[Constraints]) -> Vec<Rect>;
+
+/// Compute the geometry of the clients with constraints `clients` in
+/// `layout`.
+pub fn arrange(
+    layout: Layout,
+    params: &Params,
+    clients: &[Constraints],
+) -> Vec<Rect> {
+    arrange_smart(layout, params, clients, clients.len())
+}
+
+/// Like [arrange], but applying `smartgaps` as if there were `smart_n`
+/// clients. This lets layouts like monocle, which only ever show one client,
+/// drop the gaps no matter how many clients there are.
+pub fn arrange_smart(
+    layout: Layout,
+    params: &Params,
+    clients: &[Constraints],
+    smart_n: usize,
+) -> Vec<Rect> {
+    if clients.is_empty() {
+        return Vec::new();
+    }
+    let (gi, go) = if params.smartgaps && smart_n == 1 {
+        (0, 0)
+    } else {
+        (params.gappi, params.gappo)
+    };
+    // lay out the area inside the outer gaps, extended by one inner gap so
+    // that trimming the inner gap from the right and bottom of each cell
+    // leaves exactly one gap between neighbors and none at the edges
+    let a = params.area;
+    let inner = Params {
+        area: Rect {
+            x: a.x + go,
+            y: a.y + go,
+            w: a.w - 2 * go + gi,
+            h: a.h - 2 * go + gi,
+        },
+        gappi: 0,
+        gappo: 0,
+        smartgaps: false,
+        ..*params
+    };
+    let cells: Vec<_> = clients
+        .iter()
+        .map(|c| Constraints {
+            bw: 0,
+            minw: if c.minw > 0 { c.minw + 2 * c.bw + gi } else { 0 },
+            minh: if c.minh > 0 { c.minh + 2 * c.bw + gi } else { 0 },
+            cfact: c.cfact,
+        })
+        .collect();
+    layout(&inner, &cells)
+        .into_iter()
+        .zip(clients)
+        .map(|(r, c)| Rect {
+            w: max(r.w, c.minw + 2 * c.bw + gi) - gi - 2 * c.bw,
+            h: max(r.h, c.minh + 2 * c.bw + gi) - gi - 2 * c.bw,
+            ..r
+        })
+        .collect()
+}
+
+/// Split `len` pixels starting at `start` into spans, one for each of the
+/// `(minimum, weight)` pairs in `spans`, with lengths proportional to their
+/// weights. Returns the start and length of each. Like dwm's `tile`, a span
+/// longer than its share because of its minimum pushes the following spans
+/// along, as long as it doesn't reach the end.
+fn split(
+    start: c_int,
+    len: c_int,
+    spans: &[(c_int, f32)],
+) -> Vec<(c_int, c_int)> {
+    let mut ret = Vec::with_capacity(spans.len());
+    let mut weight: f64 = spans.iter().map(|&(_, w)| w as f64).sum();
+    let mut used = 0;
+    for &(min, w) in spans {
+        let l = ((len - used) as f64 * w as f64 / weight) as c_int;
+        weight -= w as f64;
+        ret.push((start + used, l));
+        let l = max(l, min);
+        if used + l < len {
+            used += l;
+        }
+    }
+    ret
+}
+
+/// One rectangle for each of `cs` stacked vertically in `r`.
+fn column(r: Rect, cs: &[Constraints]) -> Vec<Rect> {
+    let spans: Vec<_> = cs.iter().map(|c| (c.minh, c.cfact)).collect();
+    split(r.y, r.h, &spans)
+        .into_iter()
+        .map(|(y, h)| Rect { y, h, ..r })
+        .collect()
+}
+
+/// One rectangle for each of `cs` side by side in `r`.
+fn row(r: Rect, cs: &[Constraints]) -> Vec<Rect> {
+    let spans: Vec<_> = cs.iter().map(|c| (c.minw, c.cfact)).collect();
+    split(r.x, r.w, &spans)
+        .into_iter()
+        .map(|(x, w)| Rect { x, w, ..r })
+        .collect()
+}
+
+impl Params {
+    /// The number of clients in the master area out of `n`.
+    fn masters(&self, n: usize) -> usize {
+        min(n, self.nmaster)
+    }
+
+    /// Split the area into a master and stack area, dividing the width if
+    /// `horizontal` and the height otherwise. Either is `None` when it holds
+    /// no clients.
+    fn master_stack(
+        &self,
+        n: usize,
+        horizontal: bool,
+    ) -> (Option<Rect>, Option<Rect>) {
+        let a = self.area;
+        let nm = self.masters(n);
+        if nm == 0 {
+            return (None, Some(a));
+        }
+        if n == nm {
+            return (Some(a), None);
+        }
+        if horizontal {
+            let mw = (a.w as f32 * self.mfact) as c_int;
+            (
+                Some(Rect { w: mw, ..a }),
+                Some(Rect { x: a.x + mw, w: a.w - mw, ..a }),
+            )
+        } else {
+            let mh = (a.h as f32 * self.mfact) as c_int;
+            (
+                Some(Rect { h: mh, ..a }),
+                Some(Rect { y: a.y + mh, h: a.h - mh, ..a }),
+            )
+        }
+    }
+}
+
+/// Every client fills the whole area.
+pub fn monocle(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    vec![p.area; cs.len()]
+}
+
+/// Master area on the left with its clients stacked vertically, and the stack
+/// on the right with its clients stacked vertically.
+pub fn tile(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    let (ms, ss) = cs.split_at(p.masters(cs.len()));
+    let (master, stack) = p.master_stack(cs.len(), true);
+    let mut ret = master.map(|r| column(r, ms)).unwrap_or_default();
+    ret.extend(stack.map(|r| column(r, ss)).unwrap_or_default());
+    ret
+}
+
+/// Master area on top with its clients side by side, and the stack below with
+/// its clients side by side.
+pub fn bstack(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    let (ms, ss) = cs.split_at(p.masters(cs.len()));
+    let (master, stack) = p.master_stack(cs.len(), false);
+    let mut ret = master.map(|r| row(r, ms)).unwrap_or_default();
+    ret.extend(stack.map(|r| row(r, ss)).unwrap_or_default());
+    ret
+}
+
+/// Like [bstack], but with the stack clients stacked vertically.
+pub fn bstackhoriz(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    let (ms, ss) = cs.split_at(p.masters(cs.len()));
+    let (master, stack) = p.master_stack(cs.len(), false);
+    let mut ret = master.map(|r| row(r, ms)).unwrap_or_default();
+    ret.extend(stack.map(|r| column(r, ss)).unwrap_or_default());
+    ret
+}
+
+/// Master area on the left like [tile], with every stack client filling the
+/// whole stack area.
+pub fn deck(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    let (ms, ss) = cs.split_at(p.masters(cs.len()));
+    let (master, stack) = p.master_stack(cs.len(), true);
+    let mut ret = master.map(|r| column(r, ms)).unwrap_or_default();
+    ret.extend(stack.map(|r| vec![r; ss.len()]).unwrap_or_default());
+    ret
+}
+
+/// Master area in the middle, with the stack clients alternating between
+/// columns to its right and left. With a single stack client, the master area
+/// is on the left instead.
+pub fn centeredmaster(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    let a = p.area;
+    let (ms, ss) = cs.split_at(p.masters(cs.len()));
+    if ss.is_empty() {
+        return column(a, ms);
+    }
+    let mw = if ms.is_empty() { 0 } else { (a.w as f32 * p.mfact) as c_int };
+    let (left, master, right) = if ss.len() > 1 {
+        let tw = (a.w - mw) / 2;
+        (
+            Rect { w: tw, ..a },
+            Rect { x: a.x + tw, w: mw, ..a },
+            Rect { x: a.x + tw + mw, w: a.w - mw - tw, ..a },
+        )
+    } else {
+        let empty = Rect { w: 0, ..a };
+        (
+            empty,
+            Rect { w: mw, ..a },
+            Rect { x: a.x + mw, w: a.w - mw, ..a },
+        )
+    };
+    let mut ret = column(master, ms);
+    // the stack clients alternate starting on the right
+    let rs: Vec<_> = ss.iter().copied().step_by(2).collect();
+    let ls: Vec<_> = ss.iter().copied().skip(1).step_by(2).collect();
+    let mut right = column(right, &rs).into_iter();
+    let mut left = column(left, &ls).into_iter();
+    for i in 0..ss.len() {
+        let r = if i % 2 == 0 { right.next() } else { left.next() };
+        ret.push(r.unwrap());
+    }
+    ret
+}
+
+/// Master area floating in the middle of the monitor, over the stack clients
+/// side by side across the whole area.
+pub fn centeredfloatingmaster(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    let a = p.area;
+    let (ms, ss) = cs.split_at(p.masters(cs.len()));
+    let master = if !ss.is_empty() {
+        // a box of mfact in the wider direction and 90% in the other
+        let (mw, mh) = if ms.is_empty() {
+            (0, 0)
+        } else if a.w > a.h {
+            ((a.w as f32 * p.mfact) as c_int, (a.h as f32 * 0.9) as c_int)
+        } else {
+            ((a.w as f32 * 0.9) as c_int, (a.h as f32 * p.mfact) as c_int)
+        };
+        Rect { x: a.x + (a.w - mw) / 2, y: a.y + (a.h - mh) / 2, w: mw, h: mh }
+    } else {
+        a
+    };
+    let mut ret = row(master, ms);
+    ret.extend(row(a, ss));
+    ret
+}
+
+/// Columns of nearly equal numbers of clients, as square as possible, with
+/// the rightmost columns taking one more client if they don't divide evenly.
+pub fn grid(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    let n = cs.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut cols = 1;
+    while cols * cols < n {
+        cols += 1;
+    }
+    // 2 columns of 2 and 3 instead of 3 columns of 1, 2, and 2
+    if n == 5 {
+        cols = 2;
+    }
+    let rows = n / cols;
+    let counts: Vec<_> = (0..cols)
+        .map(|i| if i >= cols - n % cols { rows + 1 } else { rows })
+        .collect();
+    // the minimum width of a column is the largest of its clients, and its
+    // weight is their average
+    let mut col_cs = Vec::with_capacity(cols);
+    let mut rest = cs;
+    for &k in &counts {
+        let (col, r) = rest.split_at(k);
+        let minw = col.iter().map(|c| c.minw).max().unwrap_or(0);
+        let cfact = col.iter().map(|c| c.cfact).sum::<f32>() / k as f32;
+        col_cs.push((col, Constraints { minw, cfact, ..Default::default() }));
+        rest = r;
+    }
+    let widths: Vec<_> = col_cs.iter().map(|(_, c)| *c).collect();
+    let mut ret = Vec::with_capacity(n);
+    for (r, (col, _)) in row(p.area, &widths).into_iter().zip(col_cs) {
+        ret.extend(column(r, col));
+    }
+    ret
+}
+
+/// Each client takes half of the space left by the one before it, turning
+/// clockwise around the center of the monitor.
+pub fn spiral(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    fibonacci(p, cs, false)
+}
+
+/// Each client takes half of the space left by the one before it, moving
+/// towards the bottom right of the monitor.
+pub fn dwindle(p: &Params, cs: &[Constraints]) -> Vec<Rect> {
+    fibonacci(p, cs, true)
+}
+
+/// The first client takes `mfact` of the width, and each client after that
+/// splits the remaining space with the rest, alternating between splitting the
+/// width and the height. A client with a `cfact` of 2 takes two thirds of the
+/// space instead of half.
+fn fibonacci(p: &Params, cs: &[Constraints], dwindle: bool) -> Vec<Rect> {
+    let mut rest = p.area;
+    let mut splitting = true;
+    let mut ret = Vec::with_capacity(cs.len());
+    for (i, c) in cs.iter().enumerate() {
+        // split the width for even clients and the height for odd ones
+        let (len, min) =
+            if i % 2 == 0 { (rest.w, c.minw) } else { (rest.h, c.minh) };
+        // stop splitting once the halves would be too small, stacking the
+        // rest of the clients on the last piece
+        if len / 2 <= min {
+            splitting = false;
+        }
+        if !splitting || i == cs.len() - 1 {
+            ret.push(rest);
+            continue;
+        }
+        let f = if i == 0 { p.mfact } else { c.cfact / (c.cfact + 1.0) };
+        let l = (len as f32 * f) as c_int;
+        // dwindle always takes the left or top piece, while spiral turns
+        // clockwise: left, top, right, bottom
+        let first = dwindle || i % 4 < 2;
+        let (client, remaining) = match (i % 2 == 0, first) {
+            (true, true) => (
+                Rect { w: l, ..rest },
+                Rect { x: rest.x + l, w: rest.w - l, ..rest },
+            ),
+            (true, false) => (
+                Rect { x: rest.x + rest.w - l, w: l, ..rest },
+                Rect { w: rest.w - l, ..rest },
+            ),
+            (false, true) => (
+                Rect { h: l, ..rest },
+                Rect { y: rest.y + l, h: rest.h - l, ..rest },
+            ),
+            (false, false) => (
+                Rect { y: rest.y + rest.h - l, h: l, ..rest },
+                Rect { h: rest.h - l, ..rest },
+            ),
+        };
+        ret.push(client);
+        rest = remaining;
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    const PARAMS: Params = Params {
+        area: Rect { x: 0, y: 0, w: 1920, h: 1080 },
+        mfact: 0.55,
+        nmaster: 1,
+        gappi: 0,
+        gappo: 0,
+        smartgaps: false,
+    };
+
+    fn write_rects(out: &mut String, rects: Vec<Rect>) {
+        for Rect { x, y, w, h } in rects {
+            writeln!(out, "    {x:>4} {y:>4} {w:>4} {h:>4}").unwrap();
+        }
+    }
+
+    /// Render the output of `layout` on a 1920x1080 monitor for a range of
+    /// client and master counts.
+    fn render(layout: Layout) -> String {
+        let mut out = String::new();
+        for nmaster in [1, 2] {
+            for n in 1..=5 {
+                let p = Params { nmaster, ..PARAMS };
+                writeln!(out, "nmaster = {nmaster}, n = {n}").unwrap();
+                let cs = vec![Constraints::default(); n];
+                write_rects(&mut out, arrange(layout, &p, &cs));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn tile() {
+        assert_snapshot!(render(super::tile));
+    }
+
+    #[test]
+    fn monocle() {
+        assert_snapshot!(render(super::monocle));
+    }
+
+    #[test]
+    fn placement() {
+        let area = Rect { x: 1920, y: 20, w: 1920, h: 1060 };
+        assert_eq!(
+            Placement::default().place(area, 800, 600, 2),
+            Rect { x: 2478, y: 248, w: 800, h: 600 }
+        );
+        let p =
+            Placement { w: Some(0.5), h: Some(400.0), ..Default::default() };
+        assert_eq!(
+            p.place(area, 800, 600, 2),
+            Rect { x: 2400, y: 350, w: 956, h: 396 }
+        );
+        let p = Placement { x: Some(0.0), y: Some(10.0), ..p };
+        assert_eq!(
+            p.place(area, 800, 600, 2),
+            Rect { x: 1920, y: 30, w: 956, h: 396 }
+        );
+    }
+
+    #[test]
+    fn smartgaps() {
+        let p = Params { gappi: 10, gappo: 20, smartgaps: true, ..PARAMS };
+        let one = [Constraints::default()];
+        let full = vec![Rect { x: 0, y: 0, w: 1920, h: 1080 }];
+        assert_eq!(arrange(super::tile, &p, &one), full);
+        // monocle drops the gaps however many clients there are
+        let three = [Constraints::default(); 3];
+        assert_eq!(
+            arrange_smart(super::monocle, &p, &three, 1),
+            vec![full[0]; 3]
+        );
+        let gapped = Rect { x: 20, y: 20, w: 1880, h: 1040 };
+        assert_eq!(arrange(super::monocle, &p, &three), vec![gapped; 3]);
+    }
+
+    #[test]
+    fn empty() {
+        assert!(arrange(super::tile, &PARAMS, &[]).is_empty());
+    }
+
+    #[test]
+    fn gaps_and_borders() {
+        let p = Params { gappi: 10, gappo: 20, ..PARAMS };
+        let cs = [Constraints { bw: 2, ..Default::default() }; 3];
+        let mut out = String::new();
+        write_rects(&mut out, arrange(super::tile, &p, &cs));
+        assert_snapshot!(out, @r"
+           20   20 1025 1036
+         1059   20  837  511
+         1059  545  837  511
+        ");
+    }
+
+    #[test]
+    fn cfact() {
+        // the second stack client takes twice the height of the others, and
+        // the master client doesn't affect the stack
+        let cs = [
+            Constraints { cfact: 3.0, ..Default::default() },
+            Constraints::default(),
+            Constraints { cfact: 2.0, ..Default::default() },
+            Constraints::default(),
+        ];
+        let mut out = String::new();
+        for layout in [super::tile, super::bstack, super::dwindle] {
+            write_rects(&mut out, arrange(layout, &PARAMS, &cs));
+            writeln!(out).unwrap();
+        }
+        assert_snapshot!(out, @r"
+            0    0 1056 1080
+         1056    0  864  270
+         1056  270  864  540
+         1056  810  864  270
+
+            0    0 1920  594
+            0  594  480  486
+          480  594  960  486
+         1440  594  480  486
+
+            0    0 1056 1080
+         1056    0  864  540
+         1056  540  576  540
+         1632  540  288  540
+        ");
+    }
+
+    #[test]
+    fn min_size() {
+        // the first stack client needs more than half of the height, so it
+        // pushes the second one down
+        let cs = [
+            Constraints::default(),
+            Constraints { minh: 700, ..Default::default() },
+            Constraints::default(),
+        ];
+        let mut out = String::new();
+        write_rects(&mut out, arrange(super::tile, &PARAMS, &cs));
+        assert_snapshot!(out, @r"
+            0    0 1056 1080
+         1056    0  864  700
+         1056  700  864  380
+        ");
+    }
+
+    #[test]
+    fn bstack() {
+        assert_snapshot!(render(super::bstack));
+    }
+
+    #[test]
+    fn bstackhoriz() {
+        assert_snapshot!(render(super::bstackhoriz));
+    }
+
+    #[test]
+    fn centeredmaster() {
+        assert_snapshot!(render(super::centeredmaster));
+    }
+
+    #[test]
+    fn centeredfloatingmaster() {
+        assert_snapshot!(render(super::centeredfloatingmaster));
+    }
+
+    #[test]
+    fn grid() {
+        assert_snapshot!(render(super::grid));
+    }
+
+    #[test]
+    fn spiral() {
+        assert_snapshot!(render(super::spiral));
+    }
+
+    #[test]
+    fn dwindle() {
+        assert_snapshot!(render(super::dwindle));
+    }
+
+    #[test]
+    fn deck() {
+        assert_snapshot!(render(super::deck));
+    }
+}
@@ -7,7 +7,10 @@ use std::{
 
 use config::key::FUNC_MAP;
 use enums::Clk;
-use layouts::{monocle, tile};
+use layouts::{
+    bstack, bstackhoriz, centeredfloatingmaster, centeredmaster, deck, dwindle,
+    geometry::Placement, grid, monocle, spiral, tile,
+};
 use x11::xft::XftColor;
 
 pub mod config;
@@ -15,13 +18,18 @@ pub mod drw;
 pub mod enums;
 pub mod events;
 pub mod handlers;
+pub mod ipc;
 pub mod key_handlers;
 pub mod layouts;
+pub mod lua;
+pub mod rules;
+pub mod status;
 pub mod util;
 pub mod x;
 pub mod xembed;
 
 pub use core::*;
+pub use rules::Rule;
 mod core;
 
 pub use state::*;
@@ -40,7 +48,7 @@ pub type Window = u64;
 pub type Clr = XftColor;
 
 #[repr(C)]
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub enum Arg {
     I(c_int),
     Ui(c_uint),
@@ -49,6 +57,11 @@ pub enum Arg {
     V(Vec<String>),
     /// CONFIG.layouts index for setlayout
     L(Option<usize>),
+    /// Index of a Lua function for the `lua` handler
+    Lua(usize),
+    /// Name of a scratchpad for togglescratch
+    #[serde(rename = "name")]
+    Name(String),
 }
 
 macro_rules! arg_getters {
@@ -69,6 +82,8 @@ impl Arg {
         F => f => f32,
         V => v => Vec<String>,
         L => l => Option<usize>,
+        Lua => lua => usize,
+        Name => name => String,
     }
 }
 
@@ -83,8 +98,8 @@ impl TryFrom<String> for ButtonFn {
         Ok(Self(Some(
             FUNC_MAP
                 .get(value.as_str())
-                .cloned()
-                .ok_or_else(|| format!("no key `{value}`"))?,
+                .map(|&(f, _)| f)
+                .ok_or_else(|| format!("unknown handler `{value}`"))?,
         )))
     }
 }
@@ -101,6 +116,7 @@ impl Debug for ButtonFn {
 #[derive(Clone, Debug, serde::Deserialize)]
 pub struct Button {
     pub click: c_uint,
+    #[serde(default)]
     pub mask: c_uint,
     pub button: c_uint,
     pub func: ButtonFn,
@@ -136,17 +152,30 @@ pub struct Cursor {
     pub cursor: x11::xlib::Cursor,
 }
 
-#[repr(C)]
+/// A window that [togglescratch](key_handlers::togglescratch) can show and hide
+/// by name, spawning it with `cmd` if it isn't running. Windows are claimed by
+/// the first scratchpad whose `class` and `title` match exactly, ignoring the
+/// empty ones.
 #[derive(Debug, Clone, serde::Deserialize)]
-pub struct Rule {
+pub struct Scratchpad {
+    pub name: String,
+    pub cmd: Vec<String>,
+    #[serde(default)]
     pub class: String,
-    pub instance: String,
+    #[serde(default)]
     pub title: String,
-    pub tags: c_uint,
-    pub isfloating: bool,
-    pub isterminal: bool,
-    pub noswallow: bool,
-    pub monitor: c_int,
+    /// Where to put the window when it is spawned or follows the selected
+    /// monitor
+    #[serde(flatten)]
+    pub placement: Placement,
+}
+
+impl Scratchpad {
+    pub fn matches(&self, class: &str, title: &str) -> bool {
+        !(self.class.is_empty() && self.title.is_empty())
+            && (self.class.is_empty() || self.class == class)
+            && (self.title.is_empty() || self.title == title)
+    }
 }
 
 pub struct Systray {
@@ -156,23 +185,65 @@ pub struct Systray {
 
 #[derive(Clone, serde::Deserialize)]
 #[serde(try_from = "String")]
-pub struct LayoutFn(pub fn(&mut State, *mut Monitor));
+pub enum LayoutFn {
+    /// One of the layouts built into rwm, by the name used to select it in
+    /// `config.lua`
+    Builtin {
+        name: String,
+        func: fn(&mut State, *mut Monitor),
+    },
+    /// Index of the Lua function implementing a layout defined in
+    /// `config.lua`. These are filled in by [LuaRuntime::set_layouts] rather
+    /// than deserialized
+    Lua(usize),
+}
+
+impl LayoutFn {
+    /// The name of a built-in layout, or `lua` for one defined in Lua.
+    pub fn name(&self) -> &str {
+        match self {
+            LayoutFn::Builtin { name, .. } => name,
+            LayoutFn::Lua(_) => "lua",
+        }
+    }
+
+    pub fn arrange(&self, state: &mut State, m: *mut Monitor) {
+        match self {
+            LayoutFn::Builtin { func, .. } => func(state, m),
+            LayoutFn::Lua(_) => lua::arrange(state, m),
+        }
+    }
+}
 
 impl TryFrom<String> for LayoutFn {
     type Error = String;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        match value.as_str() {
-            "tile" => Ok(Self(tile)),
-            "monocle" => Ok(Self(monocle)),
-            s => Err(format!("unknown layout `{s}`")),
-        }
+        let func: fn(&mut State, *mut Monitor) = match value.as_str() {
+            "tile" => tile,
+            "monocle" => monocle,
+            "bstack" => bstack,
+            "bstackhoriz" => bstackhoriz,
+            "centeredmaster" => centeredmaster,
+            "centeredfloatingmaster" => centeredfloatingmaster,
+            "grid" => grid,
+            "spiral" => spiral,
+            "dwindle" => dwindle,
+            "deck" => deck,
+            s => return Err(format!("unknown layout `{s}`")),
+        };
+        Ok(Self::Builtin { name: value, func })
     }
 }
 
 impl Debug for LayoutFn {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("LayoutFn").field(&"[func]").finish()
+        match self {
+            LayoutFn::Builtin { name, .. } => {
+                f.debug_tuple("LayoutFn").field(name).finish()
+            }
+            LayoutFn::Lua(i) => f.debug_tuple("Lua").field(i).finish(),
+        }
     }
 }
 
@@ -200,6 +271,28 @@ pub struct Pertag {
     pub ltidxs: Vec<[*const Layout; 2]>,
     /// Whether to display the bar
     pub showbars: Vec<bool>,
+    /// Gaps around tiled windows
+    pub gaps: Vec<Gaps>,
+}
+
+/// A client's entry in the title area of the bar, spanning `w` pixels from `x`.
+#[derive(Debug, Clone)]
+pub struct BarTitle {
+    pub x: c_int,
+    pub w: c_int,
+    pub client: *mut Client,
+}
+
+/// Gaps between tiled windows (`inner`) and between tiled windows and the edge
+/// of the monitor (`outer`), in pixels.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Gaps {
+    pub inner: c_int,
+    pub outer: c_int,
+    /// Whether the gaps are shown at all, for toggling them without losing
+    /// their sizes
+    pub enabled: bool,
 }
 
 #[repr(C)]
@@ -229,6 +322,10 @@ pub struct Monitor {
     pub next: *mut Monitor,
     pub barwin: Window,
     pub lt: [*const Layout; 2usize],
+    /// Clickable client entries in the title area of the bar, updated by
+    /// `drawbar`
+    pub titles: Vec<BarTitle>,
+    pub gaps: Gaps,
     pub pertag: Pertag,
 }
 
@@ -236,6 +333,11 @@ pub struct Monitor {
 #[derive(Debug, Clone)]
 pub struct Client {
     pub name: String,
+    /// The class and instance names from `WM_CLASS`, kept by
+    /// [updateclass](core::updateclass) to spare Lua layouts a round trip to
+    /// the X server for every client
+    pub class: String,
+    pub instance: String,
     pub mina: f32,
     pub maxa: f32,
     pub x: c_int,
@@ -254,6 +356,9 @@ pub struct Client {
     pub maxh: c_int,
     pub minw: c_int,
     pub minh: c_int,
+    /// Weight of the client relative to the others in its row or column of a
+    /// layout
+    pub cfact: f32,
     pub hintsvalid: bool,
     pub bw: c_int,
     pub oldbw: c_int,
@@ -262,10 +367,20 @@ pub struct Client {
     pub isfloating: bool,
     pub isurgent: bool,
     pub neverfocus: bool,
+    /// Whether a rule keeps the client from ever being focused
+    pub nofocus: bool,
     pub oldstate: bool,
     pub isfullscreen: bool,
+    /// Whether the client is hidden from its tags, but still listed on the bar
+    pub ishidden: bool,
     pub isterminal: bool,
     pub noswallow: bool,
+    /// Name of the scratchpad this client belongs to. Hidden scratchpads have
+    /// no tags
+    pub scratchpad: Option<String>,
+    /// Indices of the rules with `reapply` set that matched the client when
+    /// its rules were last applied
+    pub reapplied: Vec<usize>,
     pub pid: libc::pid_t,
     pub next: *mut Client,
     pub snext: *mut Client,
@@ -279,3 +394,28 @@ pub struct Cursors {
     pub resize: Cursor,
     pub move_: Cursor,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scratchpad_matches() {
+        let sp = |class: &str, title: &str| Scratchpad {
+            name: "sp".into(),
+            cmd: Vec::new(),
+            class: class.into(),
+            title: title.into(),
+            placement: Placement::default(),
+        };
+        assert!(sp("st", "").matches("st", "anything"));
+        assert!(!sp("st", "").matches("st-256color", "anything"));
+        assert!(sp("", "scratch").matches("st", "scratch"));
+        assert!(sp("st", "scratch").matches("st", "scratch"));
+        assert!(!sp("st", "scratch").matches("st", "other"));
+        assert!(!sp("st", "scratch").matches("xterm", "scratch"));
+        // a scratchpad without either would claim every window
+        assert!(!sp("", "").matches("st", "scratch"));
+        assert!(!sp("", "").matches("", ""));
+    }
+}
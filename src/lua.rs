@@ -0,0 +1,493 @@
+//! Lua callbacks and the scripting API available to them.
+//!
+//! Lua functions used as the `func` of a key or button are collected into a
+//! [LuaRuntime] when the config is loaded and replaced by the `lua` handler
+//! with an [Arg::Lua] index, so they can be called long after the config
+//! itself has been deserialized. While a callback runs, it can manipulate the
+//! window manager through functions on the `rwm` table:
+//!
+//! - `rwm.view(n)` views tag `n`, starting from 1, or the previous tags for 0
+//! - `rwm.spawn{cmd, args...}` runs a command like the `spawn` handler
+//! - `rwm.focused()` returns a table describing the focused client, or `nil`
+//! - `rwm.settags(window, tags)` moves a client to the tags in the bit mask
+//!   `tags`
+//! - `rwm.setfloating(window, floating)` makes a client floating or tiled
+//!
+//! Lua functions used as the `arrange` function of a layout are collected in
+//! the same way and called by [arrange].
+//!
+//! The config itself can also register hooks with `rwm.on(event, f)`, which
+//! are called with the API available when one of the [HOOKS] occurs.
+
+use std::{
+    ffi::{c_int, c_uint},
+    fmt::Debug,
+};
+
+use mlua::{FromLua, Function, IntoLuaMulti, Table, Value};
+
+use crate::{
+    Arg, Client, Layout, LayoutFn, Monitor, State, Window, focus, key_handlers,
+    layouts, nexttiled, resize, wintoclient,
+};
+
+/// The events accepted by `rwm.on`. Each hook receives a client table, except
+/// `focus`, which receives `nil` when no client is focused, and `tag_view`,
+/// which receives the new tag bit mask and the monitor number.
+pub const HOOKS: [&str; 5] =
+    ["manage", "unmanage", "focus", "tag_view", "urgent"];
+
+/// Registry key of the table mapping each event in [HOOKS] to the functions
+/// registered for it.
+const HOOKS_KEY: &str = "rwm.hooks";
+
+/// The Lua interpreter that loaded the config, kept alive along with the
+/// callbacks registered from it.
+#[derive(Default)]
+pub struct LuaRuntime {
+    lua: mlua::Lua,
+    callbacks: Vec<Function>,
+    /// The Lua layout functions, with the index of their entry in
+    /// `rwm.layouts`
+    layouts: Vec<(usize, Function)>,
+    /// The last window passed to the `focus` hooks
+    focused: Option<Window>,
+}
+
+impl Debug for LuaRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LuaRuntime")
+            .field("callbacks", &self.callbacks.len())
+            .field("layouts", &self.layouts.len())
+            .finish()
+    }
+}
+
+impl LuaRuntime {
+    /// Take ownership of `lua`, replacing any function in the `func` field of
+    /// the `keys` and `buttons` tables in `rwm` with a callback for the `lua`
+    /// handler. Functions in the `arrange` field of `layouts` are removed and
+    /// restored by [LuaRuntime::set_layouts] after deserializing the layouts.
+    pub(crate) fn new(lua: mlua::Lua, rwm: &Table) -> mlua::Result<Self> {
+        let mut callbacks = Vec::new();
+        for field in ["keys", "buttons"] {
+            // leave reporting a missing or malformed field to the deserializer
+            let Ok(bindings) = rwm.get::<Table>(field) else {
+                continue;
+            };
+            for binding in bindings.sequence_values::<Value>() {
+                let Value::Table(binding) = binding? else {
+                    continue;
+                };
+                if let Value::Function(f) = binding.get("func")? {
+                    let arg = lua.create_table()?;
+                    arg.set("Lua", callbacks.len())?;
+                    binding.set("func", "lua")?;
+                    binding.set("arg", arg)?;
+                    callbacks.push(f);
+                }
+            }
+        }
+
+        let mut layouts = Vec::new();
+        if let Ok(entries) = rwm.get::<Table>("layouts") {
+            for (i, layout) in entries.sequence_values::<Value>().enumerate() {
+                let Value::Table(layout) = layout? else {
+                    continue;
+                };
+                if let Value::Function(f) = layout.get("arrange")? {
+                    layout.set("arrange", Value::Nil)?;
+                    layouts.push((i, f));
+                }
+            }
+        }
+
+        Ok(Self { lua, callbacks, layouts, focused: None })
+    }
+
+    /// Point the deserialized `layouts` taken from Lua functions in
+    /// [LuaRuntime::new] back at those functions.
+    pub(crate) fn set_layouts(&self, layouts: &mut [Layout]) {
+        for (id, &(i, _)) in self.layouts.iter().enumerate() {
+            if let Some(layout) = layouts.get_mut(i) {
+                layout.arrange = Some(LayoutFn::Lua(id));
+            }
+        }
+    }
+}
+
+/// Install the scripting API on the `rwm` table.
+pub(crate) fn install_api(lua: &mlua::Lua, rwm: &Table) -> mlua::Result<()> {
+    if lua
+        .named_registry_value::<Option<Table>>(HOOKS_KEY)?
+        .is_none()
+    {
+        lua.set_named_registry_value(HOOKS_KEY, lua.create_table()?)?;
+    }
+    let api = lua.create_table()?;
+    api.set("view", lua.create_function(view)?)?;
+    api.set("spawn", lua.create_function(spawn)?)?;
+    api.set("focused", lua.create_function(focused)?)?;
+    api.set("settags", lua.create_function(settags)?)?;
+    api.set("setfloating", lua.create_function(setfloating)?)?;
+    api.set("on", lua.create_function(on)?)?;
+    api.set("escape", lua.create_function(escape)?)?;
+    // exposing the API through __index keeps it out of the fields seen by the
+    // Config deserializer
+    let mt = lua.create_table()?;
+    mt.set("__index", api)?;
+    rwm.set_metatable(Some(mt))
+}
+
+/// Pointer to the [State] available to the API while a callback runs.
+struct StatePtr(*mut State);
+
+/// Run `f` with the API functions able to access `state`.
+fn enter<R>(lua: &mlua::Lua, state: &mut State, f: impl FnOnce() -> R) -> R {
+    // restore the previous pointer afterwards in case this is nested inside
+    // another callback
+    let prev = lua.set_app_data(StatePtr(state));
+    let ret = f();
+    match prev {
+        Some(prev) => lua.set_app_data(prev),
+        None => lua.remove_app_data::<StatePtr>(),
+    };
+    ret
+}
+
+/// Call the API implementation `f` with the [State] of the running callback.
+fn with_state<R>(
+    lua: &mlua::Lua,
+    f: impl FnOnce(&mut State) -> mlua::Result<R>,
+) -> mlua::Result<R> {
+    let state =
+        lua.app_data_ref::<StatePtr>().map(|s| s.0).ok_or_else(|| {
+            mlua::Error::runtime("the rwm API is only available in callbacks")
+        })?;
+    f(unsafe { &mut *state })
+}
+
+/// Call the Lua callback with index `id` in `state.config.lua`.
+pub fn callback(state: &mut State, id: usize) {
+    let runtime = &state.config.lua;
+    let Some(f) = runtime.callbacks.get(id).cloned() else {
+        log::error!("no Lua callback with index {id}");
+        return;
+    };
+    let lua = runtime.lua.clone();
+    if let Err(e) = enter(&lua, state, || f.call::<()>(())) {
+        log::error!("error in Lua callback: {e}");
+    }
+}
+
+/// Call each function registered for `event` with the arguments built by
+/// `args`, which is skipped entirely if there are none.
+fn run_hooks<A: IntoLuaMulti + Clone>(
+    state: &mut State,
+    event: &str,
+    args: impl FnOnce(&mlua::Lua, &mut State) -> mlua::Result<A>,
+) {
+    let lua = state.config.lua.lua.clone();
+    let hooks = || -> mlua::Result<_> {
+        let hooks: Table = lua.named_registry_value(HOOKS_KEY)?;
+        let Some(hooks) = hooks.get::<Option<Table>>(event)? else {
+            return Ok(None);
+        };
+        let hooks = hooks.sequence_values().collect::<Result<Vec<_>, _>>()?;
+        if hooks.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((hooks, args(&lua, state)?)))
+    };
+    let (hooks, args): (Vec<Function>, A) = match hooks() {
+        Ok(Some(hooks)) => hooks,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("failed to prepare {event} hooks: {e}");
+            return;
+        }
+    };
+    for f in hooks {
+        if let Err(e) = enter(&lua, state, || f.call::<()>(args.clone())) {
+            log::error!("error in {event} hook: {e}");
+        }
+    }
+}
+
+/// Arrange the tiled clients on `m` with the Lua layout selected there.
+///
+/// The layout function is called with a table of the monitor's `wx`, `wy`,
+/// `ww`, `wh`, `mfact`, `nmaster`, and the inner and outer gaps `gappi` and
+/// `gappo`, and a list of client tables for the tiled clients. It returns a
+/// list of rectangles, either `{x, y, w, h}` or `{x = x, y = y, w = w, h = h}`,
+/// one for each client in the same order. These include the client borders,
+/// like the area of the monitor. If the function fails or returns the wrong
+/// number of rectangles, the clients are tiled instead.
+pub fn arrange(state: &mut State, m: *mut Monitor) {
+    log::trace!("lua::arrange");
+    let (id, area) = unsafe {
+        let m = &*m;
+        let id = match (*m.lt[m.sellt]).arrange {
+            Some(LayoutFn::Lua(id)) => Some(id),
+            _ => None,
+        };
+        (id, (m.wx, m.wy, m.ww, m.wh, m.mfact, m.nmaster))
+    };
+    let Some((_, f)) =
+        id.and_then(|id| state.config.lua.layouts.get(id)).cloned()
+    else {
+        log::error!("no Lua layout on monitor, tiling instead");
+        return layouts::tile(state, m);
+    };
+    let lua = state.config.lua.lua.clone();
+
+    let mut clients = Vec::new();
+    let mut c = nexttiled(unsafe { (*m).clients });
+    while !c.is_null() {
+        clients.push(c);
+        c = nexttiled(unsafe { (*c).next });
+    }
+    if clients.is_empty() {
+        return;
+    }
+    let (gappi, gappo) = layouts::gaps(state, m, clients.len() as c_int);
+
+    let call = || -> mlua::Result<Vec<Rect>> {
+        let (wx, wy, ww, wh, mfact, nmaster) = area;
+        let mon = lua.create_table()?;
+        mon.set("wx", wx)?;
+        mon.set("wy", wy)?;
+        mon.set("ww", ww)?;
+        mon.set("wh", wh)?;
+        mon.set("mfact", mfact)?;
+        mon.set("nmaster", nmaster)?;
+        mon.set("gappi", gappi)?;
+        mon.set("gappo", gappo)?;
+        let list = lua.create_table()?;
+        for &c in &clients {
+            list.push(readonly(&lua, client_table(&lua, c)?)?)?;
+        }
+        f.call::<Vec<Rect>>((mon, list))
+    };
+    // tile the clients instead of leaving some of them wherever they were
+    let rects = match call() {
+        Ok(rects) if rects.len() == clients.len() => rects,
+        Ok(rects) => {
+            log::error!(
+                "Lua layout returned {} rectangles for {} clients, tiling \
+                 instead",
+                rects.len(),
+                clients.len()
+            );
+            return layouts::tile(state, m);
+        }
+        Err(e) => {
+            log::error!("error in Lua layout, tiling instead: {e}");
+            return layouts::tile(state, m);
+        }
+    };
+    for (c, Rect { x, y, w, h }) in clients.into_iter().zip(rects) {
+        let bw = unsafe { (*c).bw };
+        resize(state, c, x, y, w - 2 * bw, h - 2 * bw, 0);
+    }
+}
+
+/// A client rectangle returned by a Lua layout.
+struct Rect {
+    x: c_int,
+    y: c_int,
+    w: c_int,
+    h: c_int,
+}
+
+impl FromLua for Rect {
+    fn from_lua(value: Value, _: &mlua::Lua) -> mlua::Result<Self> {
+        let Value::Table(t) = value else {
+            return Err(mlua::Error::runtime(format!(
+                "expected a rectangle table, found {}",
+                value.type_name()
+            )));
+        };
+        let field = |name: &str, i: usize| -> mlua::Result<c_int> {
+            match t.get::<Option<c_int>>(name)? {
+                Some(v) => Ok(v),
+                None => t.get(i),
+            }
+        };
+        Ok(Self {
+            x: field("x", 1)?,
+            y: field("y", 2)?,
+            w: field("w", 3)?,
+            h: field("h", 4)?,
+        })
+    }
+}
+
+/// Call the `event` hooks with a table describing `c`.
+pub fn client_hook(state: &mut State, event: &str, c: *mut Client) {
+    run_hooks(state, event, |lua, _| readonly(lua, client_table(lua, c)?));
+}
+
+/// Call the `focus` hooks for `c` if it differs from the last client they
+/// were called with.
+pub fn focus_hook(state: &mut State, c: *mut Client) {
+    let window = unsafe { (!c.is_null()).then(|| (*c).win) };
+    if state.config.lua.focused == window {
+        return;
+    }
+    state.config.lua.focused = window;
+    run_hooks(state, "focus", |lua, _| {
+        if c.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(readonly(lua, client_table(lua, c)?)?))
+    });
+}
+
+/// Call the `tag_view` hooks with the selected tags on `m`.
+pub fn tag_view_hook(state: &mut State, m: *mut Monitor) {
+    let (tags, num) = unsafe { ((*m).tagset[(*m).seltags], (*m).num) };
+    run_hooks(state, "tag_view", |_, _| Ok((tags, num)));
+}
+
+/// Wrap `t` in a proxy table that raises an error on assignment.
+fn readonly(lua: &mlua::Lua, t: Table) -> mlua::Result<Table> {
+    let mt = lua.create_table()?;
+    mt.set("__index", t)?;
+    mt.set(
+        "__newindex",
+        lua.create_function(|_, _: mlua::MultiValue| -> mlua::Result<()> {
+            Err(mlua::Error::runtime(
+                "client tables are read-only, use rwm.settags or \
+                 rwm.setfloating instead",
+            ))
+        })?,
+    )?;
+    let proxy = lua.create_table()?;
+    proxy.set_metatable(Some(mt))?;
+    Ok(proxy)
+}
+
+/// Build a table describing `c` for Lua.
+pub(crate) fn client_table(
+    lua: &mlua::Lua,
+    c: *mut Client,
+) -> mlua::Result<Table> {
+    let c = unsafe { &*c };
+    let t = lua.create_table()?;
+    t.set("window", c.win)?;
+    t.set("title", c.name.as_str())?;
+    t.set("class", c.class.as_str())?;
+    t.set("instance", c.instance.as_str())?;
+    t.set("tags", c.tags)?;
+    t.set("floating", c.isfloating)?;
+    t.set("hidden", c.ishidden)?;
+    t.set("pid", c.pid)?;
+    t.set("monitor", unsafe { (*c.mon).num })?;
+    t.set("cfact", c.cfact)?;
+    Ok(t)
+}
+
+fn view(lua: &mlua::Lua, n: c_uint) -> mlua::Result<()> {
+    with_state(lua, |state| {
+        if n as usize > state.config.tags.len() {
+            return Err(mlua::Error::runtime(format!("no tag {n}")));
+        }
+        let arg = Arg::Ui(if n == 0 { 0 } else { 1 << (n - 1) });
+        key_handlers::view(state, &arg);
+        Ok(())
+    })
+}
+
+fn spawn(lua: &mlua::Lua, cmd: Vec<String>) -> mlua::Result<()> {
+    with_state(lua, |state| {
+        if cmd.is_empty() {
+            return Err(mlua::Error::runtime("spawn requires a command"));
+        }
+        key_handlers::spawn(state, &Arg::V(cmd));
+        Ok(())
+    })
+}
+
+/// Look up the client for `window`.
+fn client(state: &mut State, window: Window) -> mlua::Result<*mut Client> {
+    let c = wintoclient(state, window);
+    if c.is_null() {
+        return Err(mlua::Error::runtime(format!("no client {window:#x}")));
+    }
+    Ok(c)
+}
+
+fn settags(
+    lua: &mlua::Lua,
+    (window, tags): (Window, c_uint),
+) -> mlua::Result<()> {
+    with_state(lua, |state| {
+        let c = client(state, window)?;
+        if tags & state.tagmask() == 0 {
+            return Err(mlua::Error::runtime(format!("no tags in {tags:#x}")));
+        }
+        unsafe {
+            (*c).tags = tags & state.tagmask();
+            focus(state, std::ptr::null_mut());
+            crate::arrange(state, (*c).mon);
+        }
+        Ok(())
+    })
+}
+
+fn setfloating(
+    lua: &mlua::Lua,
+    (window, floating): (Window, bool),
+) -> mlua::Result<()> {
+    with_state(lua, |state| {
+        let c = client(state, window)?;
+        unsafe {
+            // like togglefloating, there's no support for fullscreen windows
+            if (*c).isfullscreen || (*c).isfloating == floating {
+                return Ok(());
+            }
+            (*c).isfloating = floating || (*c).isfixed;
+            if (*c).isfloating {
+                let c = &mut *c;
+                resize(state, c, c.x, c.y, c.w, c.h, 0);
+            }
+            crate::arrange(state, (*c).mon);
+        }
+        Ok(())
+    })
+}
+
+/// `s` with the regular expression syntax in it escaped, so that a rule matches
+/// it literally.
+fn escape(_: &mlua::Lua, s: String) -> mlua::Result<String> {
+    Ok(regex::escape(&s))
+}
+
+fn on(lua: &mlua::Lua, (event, f): (String, Function)) -> mlua::Result<()> {
+    if !HOOKS.contains(&event.as_str()) {
+        return Err(mlua::Error::runtime(format!(
+            "unknown event `{event}`, expected one of {HOOKS:?}"
+        )));
+    }
+    let hooks: Table = lua.named_registry_value(HOOKS_KEY)?;
+    let list = match hooks.get::<Option<Table>>(event.as_str())? {
+        Some(list) => list,
+        None => {
+            let list = lua.create_table()?;
+            hooks.set(event, &list)?;
+            list
+        }
+    };
+    list.push(f)
+}
+
+fn focused(lua: &mlua::Lua, _: ()) -> mlua::Result<Option<Table>> {
+    with_state(lua, |state| {
+        let c = unsafe { (*state.selmon).sel };
+        if c.is_null() {
+            return Ok(None);
+        }
+        client_table(lua, c).map(Some)
+    })
+}
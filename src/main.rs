@@ -1,10 +1,20 @@
 //! tiling window manager based on dwm
 
+use std::{
+    ffi::CString, os::unix::process::CommandExt, path::PathBuf,
+    process::Command,
+};
+
 use rwm::{cleanup, run, scan, setup};
 #[cfg(target_os = "linux")]
 use xcb::Connection;
 
+use log::LevelFilter;
 use rwm::checkotherwm;
+use rwm::config::{
+    Config, DEFAULT_CONFIG,
+    check::{Severity, check},
+};
 use rwm::util::die;
 
 pub use rwm::enums;
@@ -12,15 +22,139 @@ pub use rwm::enums;
 #[cfg(test)]
 mod tests;
 
+const USAGE: &str = "\
+usage: rwm [-v] [-c CONFIG] [--display DISPLAY] [--log-level LEVEL]
+       rwm --check-config [CONFIG]
+       rwm --dump-default-config
+
+  -c, --config CONFIG    load CONFIG instead of $XDG_CONFIG_HOME/rwm/config.lua
+      --display DISPLAY  manage DISPLAY instead of $DISPLAY
+      --log-level LEVEL  one of off, error, warn, info, debug, or trace,
+                         overriding $RUST_LOG
+      --check-config     report problems in CONFIG, or the config given by -c,
+                         and exit
+      --dump-default-config
+                         print the builtin config.lua and exit
+  -v, --version          print the version and exit
+  -h, --help             print this message and exit";
+
+#[derive(Default)]
+struct Args {
+    config: Option<PathBuf>,
+    display: Option<String>,
+    log_level: Option<LevelFilter>,
+    check_config: bool,
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(1);
+}
+
+/// Parse the command line, handling the flags that exit immediately.
+fn parse_args() -> Args {
+    let mut ret = Args::default();
+    let mut args = std::env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next().unwrap_or_else(|| {
+                eprintln!("rwm: {flag} requires an argument");
+                usage();
+            })
+        };
+        match arg.as_str() {
+            "-c" | "--config" => ret.config = Some(value(&arg).into()),
+            "--display" => ret.display = Some(value(&arg)),
+            "--log-level" => {
+                let level = value(&arg);
+                match level.parse() {
+                    Ok(level) => ret.log_level = Some(level),
+                    Err(_) => {
+                        eprintln!("rwm: invalid log level `{level}`");
+                        usage();
+                    }
+                }
+            }
+            "--check-config" => {
+                ret.check_config = true;
+                if let Some(path) = args.next_if(|a| !a.starts_with('-')) {
+                    ret.config = Some(path.into());
+                }
+            }
+            "--dump-default-config" => {
+                print!("{DEFAULT_CONFIG}");
+                std::process::exit(0);
+            }
+            "-v" | "--version" => {
+                println!("rwm-{}", env!("CARGO_PKG_VERSION"));
+                std::process::exit(0);
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ => {
+                eprintln!("rwm: unrecognized argument `{arg}`");
+                usage();
+            }
+        }
+    }
+    ret
+}
+
+/// Check the config file at `path`, or the default location, printing any
+/// problems to stderr and exiting with a failure status if any are errors.
+fn check_config(path: Option<PathBuf>) -> ! {
+    let Some(path) = path.or_else(Config::home_path) else {
+        die("rwm: unable to determine config directory");
+    };
+    let diagnostics = check(&path);
+    for d in &diagnostics {
+        eprintln!("{d}");
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    if diagnostics.is_empty() {
+        eprintln!("{}: ok", path.display());
+    } else {
+        eprintln!("{errors} error(s), {warnings} warning(s)");
+    }
+    std::process::exit(if errors > 0 { 1 } else { 0 });
+}
+
 fn main() {
-    env_logger::init();
-    let dpy = unsafe { x11::xlib::XOpenDisplay(std::ptr::null_mut()) };
+    let args = parse_args();
+
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = args.log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
+
+    if args.check_config {
+        check_config(args.config);
+    }
+
+    let display = args.display.map(|display| {
+        // so that clients spawned by rwm end up on the same display
+        unsafe { std::env::set_var("DISPLAY", &display) };
+        CString::new(display)
+            .unwrap_or_else(|_| die("rwm: invalid display name"))
+    });
+    let dpy = unsafe {
+        x11::xlib::XOpenDisplay(
+            display.as_ref().map_or(std::ptr::null(), |d| d.as_ptr()),
+        )
+    };
     if dpy.is_null() {
         die("rwm: cannot open display");
     }
 
     checkotherwm(dpy);
-    let mut state = setup(dpy);
+    let mut state = setup(dpy, args.config);
 
     #[cfg(target_os = "linux")]
     {
@@ -32,5 +166,14 @@ fn main() {
 
     scan(&mut state);
     run(&mut state);
+    let restart = state.restart;
     cleanup(state);
+
+    if restart {
+        // exec argv[0] rather than current_exe so that an upgraded binary on
+        // the PATH replaces the running one
+        let mut args = std::env::args_os();
+        let err = Command::new(args.next().unwrap()).args(args).exec();
+        die(&format!("rwm: failed to restart: {err}"));
+    }
 }
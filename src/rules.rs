@@ -0,0 +1,346 @@
+//! Window rules and how they combine, kept apart from X so that they can be
+//! tested directly.
+
+use std::ffi::{c_int, c_uint};
+
+use crate::layouts::geometry::Placement;
+
+/// A regular expression that a window property is matched against. It only
+/// has to match part of the property, and an empty pattern matches anything.
+#[derive(Clone, Default, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(Option<regex::Regex>);
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(Self(None));
+        }
+        regex::Regex::new(&value).map(|re| Self(Some(re)))
+    }
+}
+
+impl std::fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.as_ref().map_or("", |re| re.as_str()).fmt(f)
+    }
+}
+
+impl Pattern {
+    pub fn matches(&self, s: &str) -> bool {
+        self.0.as_ref().is_none_or(|re| re.is_match(s))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Rule {
+    #[serde(default)]
+    pub class: Pattern,
+    #[serde(default)]
+    pub instance: Pattern,
+    #[serde(default)]
+    pub title: Pattern,
+    /// Matches `WM_WINDOW_ROLE`
+    #[serde(default)]
+    pub role: Pattern,
+    /// Matches `_NET_WM_WINDOW_TYPE` without the `_NET_WM_WINDOW_TYPE_`
+    /// prefix and in lower case, like `dialog` or `splash`
+    #[serde(default, rename = "type")]
+    pub wintype: Pattern,
+    #[serde(default)]
+    pub tags: c_uint,
+    #[serde(default)]
+    pub isfloating: bool,
+    #[serde(default)]
+    pub isterminal: bool,
+    #[serde(default)]
+    pub noswallow: bool,
+    #[serde(default = "default_monitor")]
+    pub monitor: c_int,
+    /// Where to put the window when it floats
+    #[serde(flatten)]
+    pub placement: Placement,
+    /// Center the window when it floats, keeping its own size
+    #[serde(default)]
+    pub center: bool,
+    #[serde(default)]
+    pub isfullscreen: bool,
+    /// Border width of the window, instead of `borderpx`
+    #[serde(default)]
+    pub borderpx: Option<c_uint>,
+    /// Never give the window the focus
+    #[serde(default)]
+    pub neverfocus: bool,
+    /// View the tags of the window when it appears
+    #[serde(default)]
+    pub switchtotag: bool,
+    /// Apply the tags, monitor, and floating state of this rule again when
+    /// a window starts matching it after its class or title changes
+    #[serde(default)]
+    pub reapply: bool,
+}
+
+impl Rule {
+    pub fn matches(&self, p: &WindowProps) -> bool {
+        self.title.matches(&p.title)
+            && self.class.matches(&p.class)
+            && self.instance.matches(&p.instance)
+            && self.role.matches(&p.role)
+            && self.wintype.matches(&p.wintype)
+    }
+}
+
+/// The properties of a window that rules match against.
+pub struct WindowProps {
+    pub class: String,
+    pub instance: String,
+    pub title: String,
+    pub role: String,
+    pub wintype: String,
+}
+
+/// Rules without a monitor leave windows on the selected one
+fn default_monitor() -> c_int {
+    -1
+}
+
+/// What the rules matching a window want done with it. Later rules override
+/// the flags set by earlier ones, while tags accumulate.
+#[derive(Debug, Default, PartialEq)]
+pub struct RuleActions {
+    pub tags: c_uint,
+    pub isfloating: bool,
+    pub isterminal: bool,
+    pub noswallow: bool,
+    /// The monitor of the last rule that sets one
+    pub monitor: Option<c_int>,
+    pub placement: Option<Placement>,
+    pub isfullscreen: bool,
+    pub borderpx: Option<c_uint>,
+    pub neverfocus: bool,
+    pub switchtotag: bool,
+    /// Indices of the matching rules with `reapply` set
+    pub reapply: Vec<usize>,
+}
+
+/// Combine the actions of the rules in `rules` that match `props`.
+pub fn matchrules(rules: &[Rule], props: &WindowProps) -> RuleActions {
+    combine(rules.iter().enumerate(), props)
+}
+
+/// Combine the actions of the rules with `reapply` set that match `props`,
+/// unless they are the same rules as `prev`, the ones that matched last time.
+pub fn rematchrules(
+    rules: &[Rule],
+    props: &WindowProps,
+    prev: &[usize],
+) -> Option<RuleActions> {
+    let ret =
+        combine(rules.iter().enumerate().filter(|(_, r)| r.reapply), props);
+    (ret.reapply != prev).then_some(ret)
+}
+
+fn combine<'a>(
+    rules: impl Iterator<Item = (usize, &'a Rule)>,
+    props: &WindowProps,
+) -> RuleActions {
+    let mut ret = RuleActions::default();
+    for (i, r) in rules {
+        if !r.matches(props) {
+            continue;
+        }
+        if r.reapply {
+            ret.reapply.push(i);
+        }
+        ret.tags |= r.tags;
+        ret.isfloating = r.isfloating;
+        ret.isterminal = r.isterminal;
+        ret.noswallow = r.noswallow;
+        if r.monitor >= 0 {
+            ret.monitor = Some(r.monitor);
+        }
+        if r.center || r.placement != Placement::default() {
+            ret.placement = Some(r.placement);
+        }
+        ret.isfullscreen |= r.isfullscreen;
+        if r.borderpx.is_some() {
+            ret.borderpx = r.borderpx;
+        }
+        ret.neverfocus |= r.neverfocus;
+        ret.switchtotag |= r.switchtotag;
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::LuaSerdeExt as _;
+
+    use super::*;
+
+    /// Deserialize the rules in the Lua table constructor `src`.
+    fn rules(src: &str) -> Vec<Rule> {
+        let lua = mlua::Lua::new();
+        let value = lua.load(src).eval().unwrap();
+        lua.from_value(value).unwrap()
+    }
+
+    fn props(class: &str, title: &str) -> WindowProps {
+        WindowProps {
+            class: class.to_owned(),
+            instance: class.to_lowercase(),
+            title: title.to_owned(),
+            role: String::new(),
+            wintype: "normal".to_owned(),
+        }
+    }
+
+    fn pattern(s: &str) -> Pattern {
+        Pattern::try_from(s.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn empty_pattern() {
+        assert!(pattern("").matches(""));
+        assert!(pattern("").matches("anything"));
+    }
+
+    #[test]
+    fn partial_pattern() {
+        let p = pattern("fox");
+        assert!(p.matches("Firefox"));
+        assert!(p.matches("foxes"));
+        assert!(!p.matches("Fox"));
+        assert!(pattern("(?i)fox").matches("Fox"));
+        assert!(pattern("st|xterm").matches("xterm-256color"));
+    }
+
+    #[test]
+    fn anchored_pattern() {
+        let p = pattern("^st$");
+        assert!(p.matches("st"));
+        assert!(!p.matches("st-256color"));
+        assert!(!p.matches("test"));
+        assert!(pattern("^Gimp").matches("Gimp-2.10"));
+        assert!(!pattern("^Gimp").matches("gnome-Gimp"));
+    }
+
+    #[test]
+    fn literal_pattern() {
+        // strings that were matched literally before regexes can mean
+        // something else or fail to compile
+        assert!(Pattern::try_from("*scratch*".to_owned()).is_err());
+        assert!(!pattern("Firefox (Private)").matches("Firefox (Private)"));
+        let p = pattern(&regex::escape("Firefox (Private)"));
+        assert!(p.matches("Mozilla Firefox (Private)"));
+        assert!(!p.matches("Firefox Private"));
+        assert!(
+            pattern(&regex::escape("*scratch*"))
+                .matches("*scratch* - GNU Emacs")
+        );
+    }
+
+    #[test]
+    fn no_match() {
+        let rules = rules(r#"{{class = "^Gimp$", tags = 2}}"#);
+        assert_eq!(
+            matchrules(&rules, &props("firefox", "")),
+            RuleActions::default()
+        );
+    }
+
+    #[test]
+    fn role_and_type() {
+        let rules = rules(
+            r#"{
+                {role = "^pop-up$", isfloating = true},
+                {type = "^dialog$", isfloating = true, center = true},
+            }"#,
+        );
+        let mut p = props("firefox", "");
+        assert_eq!(matchrules(&rules, &p), RuleActions::default());
+        p.role = "pop-up".to_owned();
+        assert!(matchrules(&rules, &p).isfloating);
+        p.wintype = "dialog".to_owned();
+        assert_eq!(
+            matchrules(&rules, &p),
+            RuleActions {
+                isfloating: true,
+                placement: Some(Placement::default()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn actions() {
+        let rules = rules(
+            r#"{
+                {class = "st", tags = 1, isterminal = true, monitor = 1},
+                {title = "scratch", tags = 4, isfloating = true, w = 0.5,
+                 borderpx = 0, neverfocus = true},
+                {class = "st", isfullscreen = true, switchtotag = true},
+                {class = "xterm", borderpx = 5, monitor = 2},
+            }"#,
+        );
+        let got = matchrules(&rules, &props("st", "scratch"));
+        assert_eq!(
+            got,
+            RuleActions {
+                tags: 5,
+                // the last match isn't floating
+                isfloating: false,
+                isterminal: false,
+                monitor: Some(1),
+                placement: Some(Placement {
+                    w: Some(0.5),
+                    ..Default::default()
+                }),
+                isfullscreen: true,
+                borderpx: Some(0),
+                neverfocus: true,
+                switchtotag: true,
+                ..Default::default()
+            }
+        );
+        let got = matchrules(&rules, &props("st", "vim"));
+        assert_eq!(got.tags, 1);
+        assert_eq!(got.borderpx, None);
+        assert_eq!(got.placement, None);
+        assert!(!got.neverfocus);
+    }
+
+    #[test]
+    fn rematch() {
+        let rules = rules(
+            r#"{
+                {title = "mail", tags = 2, reapply = true},
+                {title = "mail", isfloating = true},
+                {title = "Inbox", tags = 4, monitor = 1, reapply = true},
+            }"#,
+        );
+        let inbox = props("firefox", "Inbox - mail");
+        let all = matchrules(&rules, &inbox);
+        assert_eq!(all.reapply, [0, 2]);
+
+        // same matches as when the window was managed, so leave it alone
+        assert_eq!(rematchrules(&rules, &inbox, &all.reapply), None);
+
+        // only the reapply rules are combined
+        let got = rematchrules(&rules, &props("firefox", "mail"), &all.reapply)
+            .unwrap();
+        assert_eq!(got.reapply, [0]);
+        assert_eq!(got.tags, 2);
+        assert!(!got.isfloating);
+        assert_eq!(got.monitor, None);
+
+        // and a window that stops matching gets an empty set once
+        let none = props("firefox", "news");
+        let got = rematchrules(&rules, &none, &[0]).unwrap();
+        assert_eq!(got, RuleActions::default());
+        assert_eq!(rematchrules(&rules, &none, &[]), None);
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     ffi::{c_int, c_uint},
     ops::Index,
+    path::PathBuf,
 };
 
 use x11::xlib::{self, Atom, Display};
@@ -12,7 +14,8 @@ use crate::{
     Clr, Cursors, Monitor, Systray, Window,
     config::Config,
     drw::{self, Drw},
-    enums::{Col, Net, Scheme, WM, XEmbed},
+    enums::{Col, Net, Rwm, Scheme, WM, XEmbed},
+    ipc::Ipc,
 };
 
 /// A color scheme.
@@ -39,6 +42,11 @@ impl ClrScheme {
     pub fn push(&mut self, clr: Vec<Clr>) {
         self.0.push(clr);
     }
+
+    /// Every color in every scheme, for freeing them.
+    pub fn into_colors(self) -> impl Iterator<Item = Clr> {
+        self.0.into_iter().flatten()
+    }
 }
 
 pub struct State {
@@ -51,12 +59,16 @@ pub struct State {
     pub wmatom: [Atom; WM::Last as usize],
     pub netatom: [Atom; Net::Last as usize],
     pub xatom: [Atom; XEmbed::Last as usize],
+    pub rwmatom: [Atom; Rwm::Last as usize],
     pub dpy: *mut Display,
     pub drw: Drw,
     pub cursors: Cursors,
     pub selmon: *mut Monitor,
     pub mons: *mut Monitor,
     pub stext: String,
+    /// The colors named by `^fg()` and `^bg()` escapes in `stext`, allocated
+    /// once each time the status text changes
+    pub statuscolors: HashMap<String, Clr>,
     pub scheme: ClrScheme,
     pub screen: c_int,
     pub root: Window,
@@ -66,8 +78,15 @@ pub struct State {
     /// Supporting window for NetWMCheck
     pub wmcheckwin: Window,
     pub running: bool,
+    /// Whether to exec a new rwm after `running` is cleared
+    pub restart: bool,
     pub numlockmask: c_uint,
     pub config: Config,
+    /// Config file given on the command line, used instead of
+    /// [Config::home_path] when reloading
+    pub config_path: Option<PathBuf>,
+    /// IPC socket, if it could be created
+    pub ipc: Option<Ipc>,
 
     #[cfg(target_os = "linux")]
     pub xcon: *mut Connection,
@@ -85,10 +104,6 @@ impl State {
     pub fn tagmask(&self) -> u32 {
         (1 << self.config.tags.len()) - 1
     }
-
-    pub fn scratchtag(&self) -> u32 {
-        1 << self.config.tags.len()
-    }
 }
 
 impl Drop for State {
@@ -0,0 +1,195 @@
+//! Escape sequences for formatting the status text.
+//!
+//! The status text, read from the name of the root window, can contain these
+//! escapes:
+//!
+//! - `^fg(#rrggbb)` and `^bg(#rrggbb)` set the foreground and background colors
+//!   of the text that follows, and `^fg()` and `^bg()` restore the default
+//! - `^r(x,y,w,h)` fills a rectangle in the foreground color, offset by `x` from
+//!   the current position and by `y` from the top of the bar. Rectangles don't
+//!   take up any space in the text, so they can be drawn behind it
+//! - `^reset()` restores both default colors
+//! - `^sig(n)` marks the text that follows as belonging to the status block
+//!   updated by the real-time signal `SIGRTMIN+n`, so that clicks on it can be
+//!   forwarded to the status program, and `^sig()` ends the block
+//! - `^^` is a literal `^`
+//!
+//! Anything else starting with `^` is drawn as-is.
+
+use std::borrow::Cow;
+use std::ffi::c_int;
+
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    /// Set the foreground color, or restore the default with `None`
+    Fg(Option<&'a str>),
+    /// Set the background color, or restore the default with `None`
+    Bg(Option<&'a str>),
+    Rect {
+        x: c_int,
+        y: c_int,
+        w: c_int,
+        h: c_int,
+    },
+    Reset,
+    /// Start a clickable block for the given signal, or end it with `None`
+    Signal(Option<c_int>),
+}
+
+/// Split `text` into runs of text and the escapes between them.
+pub fn parse(text: &str) -> Vec<Segment<'_>> {
+    let mut ret = Vec::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('^') {
+        if i > 0 {
+            ret.push(Segment::Text(&rest[..i]));
+        }
+        rest = &rest[i..];
+        if let Some(after) = rest.strip_prefix("^^") {
+            ret.push(Segment::Text("^"));
+            rest = after;
+        } else if let Some((segment, len)) = escape(rest) {
+            ret.push(segment);
+            rest = &rest[len..];
+        } else {
+            ret.push(Segment::Text("^"));
+            rest = &rest[1..];
+        }
+    }
+    if !rest.is_empty() {
+        ret.push(Segment::Text(rest));
+    }
+    ret
+}
+
+/// Parse the escape at the start of `s`, returning it along with its length.
+fn escape(s: &str) -> Option<(Segment<'_>, usize)> {
+    let open = s.find('(')?;
+    let close = open + s[open..].find(')')?;
+    let arg = &s[open + 1..close];
+    let color = (!arg.is_empty()).then_some(arg);
+    let segment = match &s[1..open] {
+        "fg" => Segment::Fg(color),
+        "bg" => Segment::Bg(color),
+        "reset" if arg.is_empty() => Segment::Reset,
+        "sig" if arg.is_empty() => Segment::Signal(None),
+        "sig" => Segment::Signal(Some(arg.trim().parse().ok()?)),
+        "r" => {
+            let mut fields = arg.split(',').map(|f| f.trim().parse().ok());
+            let mut next = || fields.next().flatten();
+            let (x, y, w, h) = (next()?, next()?, next()?, next()?);
+            if fields.next().is_some() {
+                return None;
+            }
+            Segment::Rect { x, y, w, h }
+        }
+        _ => return None,
+    };
+    Some((segment, close + 1))
+}
+
+/// `text` with any escapes removed, for measuring it.
+pub fn strip(text: &str) -> Cow<'_, str> {
+    if !text.contains('^') {
+        return Cow::Borrowed(text);
+    }
+    let mut ret = String::new();
+    for segment in parse(text) {
+        if let Segment::Text(t) = segment {
+            ret.push_str(t);
+        }
+    }
+    Cow::Owned(ret)
+}
+
+/// The signal of the block containing the point `x` pixels from the start of
+/// `text`, using `width` to measure runs of text.
+pub fn signal_at(
+    text: &str,
+    x: c_int,
+    mut width: impl FnMut(&str) -> c_int,
+) -> Option<c_int> {
+    let mut sig = None;
+    let mut end = 0;
+    for segment in parse(text) {
+        match segment {
+            Segment::Text(t) => {
+                end += width(t);
+                if x < end {
+                    return sig;
+                }
+            }
+            Segment::Signal(s) => sig = s,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment::*;
+    use super::*;
+
+    #[test]
+    fn plain() {
+        assert_eq!(parse("cpu 5% | 12:00"), vec![Text("cpu 5% | 12:00")]);
+        assert!(parse("").is_empty());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            parse("^fg(#ff0000)hot^fg() ^bg(#000)^r(0,2,4,10)x^reset()"),
+            vec![
+                Fg(Some("#ff0000")),
+                Text("hot"),
+                Fg(None),
+                Text(" "),
+                Bg(Some("#000")),
+                Rect { x: 0, y: 2, w: 4, h: 10 },
+                Text("x"),
+                Reset,
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            parse("a^^b^c ^r(1,2) ^fg(#fff"),
+            vec![
+                Text("a"),
+                Text("^"),
+                Text("b"),
+                Text("^"),
+                Text("c "),
+                Text("^"),
+                Text("r(1,2) "),
+                Text("^"),
+                Text("fg(#fff"),
+            ]
+        );
+    }
+
+    #[test]
+    fn signals() {
+        let text = "^sig(1)mail^sig() | ^sig(12)^fg(#fff)rain^sig(x)";
+        assert_eq!(strip(text), "mail | rain^sig(x)");
+        let width = |t: &str| t.len() as c_int;
+        assert_eq!(signal_at(text, 0, width), Some(1));
+        assert_eq!(signal_at(text, 3, width), Some(1));
+        assert_eq!(signal_at(text, 4, width), None);
+        assert_eq!(signal_at(text, 7, width), Some(12));
+        assert_eq!(signal_at(text, 10, width), Some(12));
+        assert_eq!(signal_at(text, 11, width), Some(12));
+        assert_eq!(signal_at(text, 100, width), None);
+    }
+
+    #[test]
+    fn stripped() {
+        assert_eq!(strip("^fg(#ff0000)a^r(0,0,1,1)^^b^bg()"), "a^b");
+        assert_eq!(strip("^x"), "^x");
+    }
+}
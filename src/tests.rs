@@ -19,7 +19,7 @@ fn main() {
     // goto for killing xephyr no matter what
     let ok = 'defer: {
         checkotherwm(dpy);
-        let mut state = setup(dpy);
+        let mut state = setup(dpy, None);
 
         #[cfg(target_os = "linux")]
         {
@@ -5,6 +5,20 @@ pub fn die(msg: &str) -> ! {
     std::process::exit(1);
 }
 
+/// Find the pid of a running process named `name`, like `pidof -s`.
+#[cfg(target_os = "linux")]
+pub fn pidof(name: &str) -> Option<libc::pid_t> {
+    std::fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .find_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let comm =
+                std::fs::read_to_string(entry.path().join("comm")).ok()?;
+            (comm.trim_end() == name).then_some(pid)
+        })
+}
+
 /// Attempt to allocate with `libc::calloc` and die if the result is null
 pub fn ecalloc(nmemb: size_t, size: size_t) -> *mut c_void {
     log::trace!("ecalloc: nmemb = {nmemb}, size = {size}");
@@ -0,0 +1,14 @@
+-- Each of these bindings has a problem for `rwm --check-config` to find
+rwm.keys = {
+   key(modkey, XK_a, "nosuchhandler"),
+   key(modkey, XK_b, view, {I = 1}),
+   key(modkey, XK_c, setlayout, {L = 99}),
+   key(modkey, XK_d, zoom),
+   key(modkey, XK_d, killclient),
+   -- without the constructor, there's no line to point at
+   {mod_ = modkey, keysym = XK_e, func = spawn, arg = {I = 1}},
+   key(modkey, XK_f, togglescratch, {V = scratchpadcmd}),
+}
+rwm.rules = {
+   rule{class = "pavucontrol", isfloating = true, x = 1, w = 0.5},
+}